@@ -1,7 +1,10 @@
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    // the descriptors of the OTLP protos, for the grpc server reflection.
+    let descriptor_path = std::path::PathBuf::from(std::env::var("OUT_DIR")?).join("opentelemetry_descriptor.bin");
     tonic_prost_build::configure()
         .build_server(true)
-        .build_client(false)
+        .build_client(true)
+        .file_descriptor_set_path(descriptor_path)
         .type_attribute(".", "#[allow(dead_code)]")
         .type_attribute(".", "#[allow(clippy::enum_variant_names)]")
         .type_attribute(".", "#[derive(serde::Deserialize)]")        
@@ -14,6 +17,19 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
             "opentelemetry/proto/collector/trace/v1/trace_service.proto",            
         ], &["."])?;
 
+    // the foreign formats have their own json model, so they don't derive Deserialize.
+    tonic_prost_build::configure()
+        .build_server(true)
+        .build_client(false)
+        .type_attribute(".", "#[allow(dead_code)]")
+        .compile_protos(&[
+            "zipkin/proto3/zipkin.proto",
+            "jaeger/api_v2/model.proto",
+            "jaeger/api_v2/collector.proto",
+            "prometheus/prompb/remote.proto",
+            "loki/logproto/push.proto",
+        ], &["."])?;
+
     build();
     Ok(())
 }
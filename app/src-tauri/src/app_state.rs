@@ -1,10 +1,20 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tonic_health::server::HealthReporter;
+use crate::config::Config;
+use crate::limits::IngestLimiter;
+use crate::metrics_exposition::MetricsExposition;
 use crate::request_processor::RequestProcessor;
 use crate::subscription_manager::SubscriptionManager;
+use crate::telemetry_store::TelemetryStore;
 
 #[derive(Clone)]
 pub(crate) struct AppState {
+    pub config: Arc<Config>,
     pub subscription_manager: Arc<RwLock<SubscriptionManager>>,
+    pub telemetry_store: Arc<RwLock<TelemetryStore>>,
     pub request_processor: Arc<RequestProcessor>,
+    pub metrics_exposition: Arc<RwLock<MetricsExposition>>,
+    pub ingest_limiter: Arc<IngestLimiter>,
+    pub health_reporter: HealthReporter,
 }
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::Form;
+use serde::Deserialize;
+use tonic::service::Interceptor;
+use tonic::Status;
+use crate::app_state::AppState;
+use crate::config::{AuthConfig, Config};
+
+const BEARER: &str = "bearer ";
+const TOKEN_COOKIE: &str = "inspector_token";
+const TOKEN_PARAMETER: &str = "token";
+const LOGIN_PAGE: &str = include_str!("login.html");
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    token: String,
+}
+
+/// Rejects the export requests without one of the ingest tokens, when some are configured.
+pub async fn require_ingest_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let auth = &state.config.auth;
+    if auth.ingest_tokens.is_empty() || has_ingest_token(auth, request.headers()) {
+        return next.run(request).await;
+    }
+    (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], "Missing or invalid ingest token").into_response()
+}
+
+/// Protects the UI, the websocket, the event stream and the metrics exposition with the UI token, when one is configured.
+/// Browsers get a login page, a valid `?token=` query parameter logs them in as well.
+pub async fn require_ui_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(ui_token) = &state.config.auth.ui_token else {
+        return next.run(request).await;
+    };
+    let headers = request.headers();
+    if bearer_token(headers).map(str::to_string).or_else(|| cookie_token(headers)).is_some_and(|token| token_eq(&token, ui_token)) {
+        return next.run(request).await;
+    }
+    if query_token(request.uri().query()).is_some_and(|token| token_eq(&token, ui_token)) {
+        let cookie = token_cookie(ui_token, state.config.tls.is_some());
+        let mut response = next.run(request).await;
+        if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+        }
+        return response;
+    }
+    let accepts_html = headers.get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if accepts_html {
+        (StatusCode::UNAUTHORIZED, Html(LOGIN_PAGE)).into_response()
+    } else {
+        (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], "Missing or invalid UI token").into_response()
+    }
+}
+
+/// Handles the login page form: a valid token is kept in a cookie and the browser goes back to the UI.
+pub async fn handle_login(State(state): State<AppState>, Form(form): Form<LoginForm>) -> Response {
+    match &state.config.auth.ui_token {
+        Some(ui_token) if token_eq(&form.token, ui_token) => {
+            let cookie = token_cookie(ui_token, state.config.tls.is_some());
+            ([(header::SET_COOKIE, cookie)], Redirect::to("/")).into_response()
+        },
+        Some(_) => (StatusCode::UNAUTHORIZED, Html(LOGIN_PAGE)).into_response(),
+        None => Redirect::to("/").into_response(),
+    }
+}
+
+/// Checks the ingest tokens of the grpc services, from the `authorization` or the api key metadata.
+#[derive(Clone)]
+pub struct IngestInterceptor {
+    config: Arc<Config>,
+}
+
+impl IngestInterceptor {
+    pub fn new(config: Arc<Config>) -> Self {
+        IngestInterceptor { config }
+    }
+}
+
+impl Interceptor for IngestInterceptor {
+    fn call(&mut self, request: tonic::Request<()>) -> Result<tonic::Request<()>, Status> {
+        let auth = &self.config.auth;
+        // the metadata of a grpc request are its http/2 headers.
+        let headers = request.metadata().clone().into_headers();
+        if auth.ingest_tokens.is_empty() || has_ingest_token(auth, &headers) {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("Missing or invalid ingest token"))
+        }
+    }
+}
+
+fn has_ingest_token(auth: &AuthConfig, headers: &HeaderMap) -> bool {
+    let api_key = headers.get(auth.api_key_header.as_str()).and_then(|value| value.to_str().ok());
+    bearer_token(headers).into_iter().chain(api_key)
+        .any(|token| auth.ingest_tokens.iter().any(|ingest_token| token_eq(token, ingest_token)))
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let authorization = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let scheme = authorization.get(..BEARER.len())?;
+    scheme.eq_ignore_ascii_case(BEARER).then(|| authorization[BEARER.len()..].trim())
+}
+
+fn cookie_token(headers: &HeaderMap) -> Option<String> {
+    headers.get_all(header::COOKIE).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|cookie| cookie.trim().strip_prefix(TOKEN_COOKIE)?.strip_prefix('='))
+        .map(percent_decode)
+}
+
+fn query_token(query: Option<&str>) -> Option<String> {
+    query?.split('&')
+        .find_map(|parameter| parameter.strip_prefix(TOKEN_PARAMETER)?.strip_prefix('='))
+        .map(percent_decode)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (escaped, bytes[i]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            },
+            (None, b'+') => decoded.push(b' '),
+            (None, byte) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// the token is encoded, a `;`, a space or a control character of it would break the header.
+fn token_cookie(token: &str, secure: bool) -> String {
+    let secure = if secure { "; Secure" } else { "" };
+    format!("{}={}; Path=/; HttpOnly; SameSite=Strict{}", TOKEN_COOKIE, percent_encode(token), secure)
+}
+
+/// Encodes everything but the unreserved characters of a url, so the text fits in a query or a cookie.
+pub(crate) fn percent_encode(text: &str) -> String {
+    text.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+// compares without returning early, so the time doesn't tell how much of the token was right.
+fn token_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn auth() -> AuthConfig {
+        AuthConfig {
+            ingest_tokens: vec!["first".to_string(), "second".to_string()],
+            api_key_header: "x-api-key".to_string(),
+            ui_token: None,
+        }
+    }
+
+    #[test]
+    fn test_ingest_token() {
+        assert!(has_ingest_token(&auth(), &headers(&[("authorization", "Bearer second")])));
+        assert!(has_ingest_token(&auth(), &headers(&[("authorization", "bearer first")])));
+        assert!(has_ingest_token(&auth(), &headers(&[("x-api-key", "first")])));
+        assert!(!has_ingest_token(&auth(), &headers(&[("authorization", "Basic first")])));
+        assert!(!has_ingest_token(&auth(), &headers(&[("authorization", "Bearer third")])));
+        assert!(!has_ingest_token(&auth(), &headers(&[])));
+    }
+
+    #[test]
+    fn test_interceptor() {
+        let config = Arc::new(Config { auth: auth(), ..Config::default() });
+        let mut interceptor = IngestInterceptor::new(config);
+        let mut request = tonic::Request::new(());
+        request.metadata_mut().insert("x-api-key", "second".parse().unwrap());
+        assert!(interceptor.call(request).is_ok());
+        let status = interceptor.call(tonic::Request::new(())).unwrap_err();
+        assert_eq!(tonic::Code::Unauthenticated, status.code());
+    }
+
+    #[test]
+    fn test_ui_token_sources() {
+        assert_eq!(Some("abc".to_string()), cookie_token(&headers(&[("cookie", "theme=dark; inspector_token=abc")])));
+        assert_eq!(None, cookie_token(&headers(&[("cookie", "inspector_token_old=abc")])));
+        assert_eq!(Some("a b/c".to_string()), query_token(Some("topics=logs&token=a+b%2Fc")));
+        assert_eq!(None, query_token(Some("tokens=abc")));
+
+        let cookie = token_cookie("a;b c+\u{7f}", true);
+        assert_eq!("inspector_token=a%3Bb%20c%2B%7F; Path=/; HttpOnly; SameSite=Strict; Secure", cookie);
+        assert!(HeaderValue::from_str(&cookie).is_ok());
+        let value = cookie.split(';').next().unwrap();
+        assert_eq!(Some("a;b c+\u{7f}".to_string()), cookie_token(&headers(&[("cookie", value)])));
+        assert!(token_eq("secret", "secret"));
+        assert!(!token_eq("secret", "secreT"));
+        assert!(!token_eq("secret", "secrets"));
+    }
+}
@@ -0,0 +1,332 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+const DEFAULT_HISTORY_SIZE: usize = 10_000;
+const DEFAULT_PAUSE_BUFFER_SIZE: usize = 5_000;
+const DEFAULT_FORWARD_QUEUE_SIZE: usize = 1_000;
+const DEFAULT_FORWARD_MAX_RETRIES: u32 = 5;
+const DEFAULT_STATSD_FLUSH_INTERVAL_SECONDS: u64 = 10;
+const DEFAULT_SCRAPE_INTERVAL_SECONDS: u64 = 15;
+// disables a receiver like an unset address, for the receivers which listened by default.
+const DISABLED: &str = "off";
+// the scheme of the unix domain socket endpoints, which the OpenTelemetry collector uses as well.
+const UNIX_SCHEME: &str = "unix://";
+const DEFAULT_API_KEY_HEADER: &str = "x-api-key";
+// the OpenTelemetry collector accepts 20 MiB http bodies by default.
+const DEFAULT_MAX_REQUEST_SIZE_MIB: usize = 20;
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 64;
+const DEFAULT_CORS_ALLOWED_METHODS: [&str; 1] = ["POST"];
+
+/// Runtime settings, read from the environment once at startup.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// How many messages of each kind (logs, traces, metrics) are kept for queries.
+    pub history_size: usize,
+    /// How many messages are kept for a websocket client while it is paused.
+    pub pause_buffer_size: usize,
+    /// Path of a unix domain socket which serves the OTLP grpc receiver, next to its tcp port.
+    pub grpc_socket: Option<String>,
+    /// Path of a unix domain socket which serves the OTLP http receiver and the UI, next to their tcp port.
+    pub http_socket: Option<String>,
+    /// Serves the grpc services on the http port, for the requests with a grpc content type, instead of their own port.
+    pub single_port: bool,
+    /// TLS of the OTLP grpc and http listeners, plain text when unset.
+    pub tls: Option<TlsConfig>,
+    /// Tokens of the receivers and of the UI.
+    pub auth: AuthConfig,
+    /// Size and concurrency limits of the export requests.
+    pub limits: LimitsConfig,
+    /// CORS of the OTLP http routes, for the exporters running in a browser. Off when unset.
+    pub cors: Option<CorsConfig>,
+    /// Upstream OTLP endpoint which receives a copy of every export request.
+    pub forward: Option<ForwardConfig>,
+    /// Address of the Zipkin v2 span receiver.
+    pub zipkin_address: Option<String>,
+    /// Address of the Jaeger Thrift over http receiver, the Jaeger grpc collector shares the OTLP grpc port.
+    pub jaeger_address: Option<String>,
+    /// Address of the syslog receiver, on udp and tcp. Disabled by default, the standard port 514 needs privileges.
+    pub syslog_address: Option<String>,
+    /// Address of the Fluentd Forward protocol receiver.
+    pub fluent_forward_address: Option<String>,
+    /// The StatsD receiver, enabled by default.
+    pub statsd: Option<StatsdConfig>,
+    /// Prometheus endpoints which are scraped on an interval.
+    pub scrape: Option<ScrapeConfig>,
+    /// Whether the http port serves the UI next to the APIs, the headless build does by default.
+    /// The desktop app only serves it to other browsers when `INSPECTOR_SERVE_UI` asks for it.
+    pub serve_ui: bool,
+    /// Directory of a built UI, served in place of the one embedded in the binary.
+    pub static_dir: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AuthConfig {
+    /// Tokens accepted from the telemetry senders, as `Authorization: Bearer <token>` or in the api key header.
+    /// Anyone can send when there are none.
+    pub ingest_tokens: Vec<String>,
+    pub api_key_header: String,
+    /// Token of the UI, the websocket, the event stream and the metrics exposition, which are open without it.
+    pub ui_token: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LimitsConfig {
+    /// The largest request body or grpc message, in bytes, after decompression.
+    pub max_request_size: usize,
+    /// How many export requests are processed at the same time, as many more wait for their turn.
+    pub max_concurrent_requests: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct CorsConfig {
+    /// The origins allowed to send, `*` allows any.
+    pub allowed_origins: Vec<String>,
+    /// The request headers allowed to send, the ones a preflight asks for when empty.
+    pub allowed_headers: Vec<String>,
+    pub allowed_methods: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TlsCertificate {
+    /// Paths of the PEM certificate chain and private key.
+    Files { cert: String, key: String },
+    /// Generated at startup for `localhost`, the loopback addresses and the host name.
+    SelfSigned,
+}
+
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub certificate: TlsCertificate,
+    /// Path of the PEM CA certificates which sign the client certificates, which are then required.
+    pub client_ca: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ForwardProtocol {
+    Grpc,
+    HttpProtobuf,
+}
+
+impl FromStr for ForwardProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grpc" => Ok(ForwardProtocol::Grpc),
+            "http/protobuf" => Ok(ForwardProtocol::HttpProtobuf),
+            _ => Err(format!("Unknown protocol: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ForwardConfig {
+    /// Base url of the upstream receiver, e.g. `http://localhost:4317` for grpc or `http://localhost:4318` for http.
+    pub endpoint: String,
+    pub protocol: ForwardProtocol,
+    /// How many export requests wait to be sent, the newer ones are dropped when the queue is full.
+    pub queue_size: usize,
+    pub max_retries: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct StatsdConfig {
+    /// The udp address.
+    pub address: String,
+    /// How long the lines are aggregated before they are published as metrics.
+    pub flush_interval: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScrapeConfig {
+    /// The urls of the `/metrics` endpoints, optionally prefixed with the job name as `job=url`.
+    pub targets: Vec<String>,
+    pub interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            history_size: DEFAULT_HISTORY_SIZE,
+            pause_buffer_size: DEFAULT_PAUSE_BUFFER_SIZE,
+            grpc_socket: None,
+            http_socket: None,
+            single_port: false,
+            tls: None,
+            auth: AuthConfig {
+                api_key_header: DEFAULT_API_KEY_HEADER.to_string(),
+                ..Default::default()
+            },
+            limits: LimitsConfig {
+                max_request_size: DEFAULT_MAX_REQUEST_SIZE_MIB * 1024 * 1024,
+                max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            },
+            cors: None,
+            forward: None,
+            zipkin_address: None,
+            jaeger_address: None,
+            syslog_address: None,
+            fluent_forward_address: None,
+            statsd: None,
+            scrape: None,
+            serve_ui: cfg!(feature = "docker"),
+            static_dir: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let default = Config::default();
+        let ingest_tokens = env_list("INSPECTOR_INGEST_TOKENS");
+        Config {
+            history_size: env_or("INSPECTOR_HISTORY_SIZE", default.history_size),
+            pause_buffer_size: env_or("INSPECTOR_PAUSE_BUFFER_SIZE", default.pause_buffer_size),
+            grpc_socket: env_socket("INSPECTOR_GRPC_SOCKET"),
+            http_socket: env_socket("INSPECTOR_HTTP_SOCKET"),
+            single_port: env_or("INSPECTOR_SINGLE_PORT", false),
+            tls: tls_certificate().map(|certificate| TlsConfig {
+                certificate,
+                client_ca: env_optional("INSPECTOR_TLS_CLIENT_CA"),
+            }),
+            auth: AuthConfig {
+                ingest_tokens: ingest_tokens.clone(),
+                api_key_header: env_optional("INSPECTOR_API_KEY_HEADER").map(|header| header.to_lowercase()).unwrap_or(default.auth.api_key_header),
+                ui_token: env_optional("INSPECTOR_UI_TOKEN"),
+            },
+            limits: LimitsConfig {
+                max_request_size: env_or("INSPECTOR_MAX_REQUEST_SIZE_MIB", DEFAULT_MAX_REQUEST_SIZE_MIB).max(1).saturating_mul(1024 * 1024),
+                max_concurrent_requests: env_or("INSPECTOR_MAX_CONCURRENT_REQUESTS", default.limits.max_concurrent_requests).max(1),
+            },
+            cors: env_optional("INSPECTOR_CORS_ALLOWED_ORIGINS").map(|_| CorsConfig {
+                allowed_origins: env_list("INSPECTOR_CORS_ALLOWED_ORIGINS"),
+                allowed_headers: env_list("INSPECTOR_CORS_ALLOWED_HEADERS"),
+                allowed_methods: Some(env_list("INSPECTOR_CORS_ALLOWED_METHODS"))
+                    .filter(|methods| !methods.is_empty())
+                    .unwrap_or_else(|| DEFAULT_CORS_ALLOWED_METHODS.map(str::to_string).to_vec()),
+            }),
+            forward: env_optional("INSPECTOR_FORWARD_ENDPOINT").map(|endpoint| ForwardConfig {
+                endpoint,
+                protocol: env_or("INSPECTOR_FORWARD_PROTOCOL", ForwardProtocol::Grpc),
+                queue_size: env_or("INSPECTOR_FORWARD_QUEUE_SIZE", DEFAULT_FORWARD_QUEUE_SIZE),
+                max_retries: env_or("INSPECTOR_FORWARD_MAX_RETRIES", DEFAULT_FORWARD_MAX_RETRIES),
+            }),
+            zipkin_address: env_optional_address("INSPECTOR_ZIPKIN_ADDRESS"),
+            jaeger_address: env_optional_address("INSPECTOR_JAEGER_ADDRESS"),
+            syslog_address: env_optional("INSPECTOR_SYSLOG_ADDRESS"),
+            fluent_forward_address: open_address("INSPECTOR_FLUENT_FORWARD_ADDRESS", &ingest_tokens),
+            statsd: open_address("INSPECTOR_STATSD_ADDRESS", &ingest_tokens).map(|address| StatsdConfig {
+                address,
+                flush_interval: Duration::from_secs(env_or("INSPECTOR_STATSD_FLUSH_INTERVAL", DEFAULT_STATSD_FLUSH_INTERVAL_SECONDS).max(1)),
+            }),
+            scrape: env_optional("INSPECTOR_SCRAPE_TARGETS").map(|_| ScrapeConfig {
+                targets: env_list("INSPECTOR_SCRAPE_TARGETS"),
+                interval: Duration::from_secs(env_or("INSPECTOR_SCRAPE_INTERVAL", DEFAULT_SCRAPE_INTERVAL_SECONDS).max(1)),
+            }),
+            serve_ui: env_or("INSPECTOR_SERVE_UI", default.serve_ui),
+            static_dir: env_optional("STATIC_DIR"),
+        }
+    }
+}
+
+fn tls_certificate() -> Option<TlsCertificate> {
+    match (env_optional("INSPECTOR_TLS_CERT"), env_optional("INSPECTOR_TLS_KEY")) {
+        (Some(cert), Some(key)) => Some(TlsCertificate::Files { cert, key }),
+        (Some(_), None) | (None, Some(_)) => {
+            println!("INSPECTOR_TLS_CERT and INSPECTOR_TLS_KEY are both needed, TLS is off.");
+            None
+        },
+        (None, None) => env_or("INSPECTOR_TLS_SELF_SIGNED", false).then_some(TlsCertificate::SelfSigned),
+    }
+}
+
+fn env_socket(name: &str) -> Option<String> {
+    env_optional(name).map(parse_socket)
+}
+
+// a path, optionally as a `unix://` endpoint.
+fn parse_socket(path: String) -> String {
+    path.strip_prefix(UNIX_SCHEME).map(str::to_string).unwrap_or(path)
+}
+
+// the address of a receiver which is off by default.
+fn env_optional_address(name: &str) -> Option<String> {
+    parse_address(env_optional(name))
+}
+
+fn parse_address(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.eq_ignore_ascii_case(DISABLED))
+}
+
+// the address of a receiver which can't check the ingest tokens, anyone may send to it even when they are set.
+fn open_address(name: &str, ingest_tokens: &[String]) -> Option<String> {
+    let address = env_optional_address(name);
+    if address.is_some() && !ingest_tokens.is_empty() {
+        println!("{} is set, the receiver doesn't check the ingest tokens.", name);
+    }
+    address
+}
+
+fn env_list(name: &str) -> Vec<String> {
+    parse_list(&env_optional(name).unwrap_or_default())
+}
+
+// the comma separated values, without the empty ones.
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn env_optional(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    parse_or(name, std::env::var(name).ok(), default)
+}
+
+fn parse_or<T: FromStr>(name: &str, value: Option<String>, default: T) -> T {
+    match value {
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            println!("Invalid value '{}' for {}, using the default one.", value, name);
+            default
+        }),
+        None => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(Some("[::]:9411".to_string()), parse_address(Some("[::]:9411".to_string())));
+        assert_eq!(None, parse_address(Some("off".to_string())));
+        assert_eq!(None, parse_address(Some("OFF".to_string())));
+        assert_eq!(None, parse_address(None));
+    }
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(vec!["first", "second"], parse_list(" first, ,second,"));
+        assert!(parse_list("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_socket() {
+        assert_eq!("/tmp/otlp.sock", parse_socket("unix:///tmp/otlp.sock".to_string()));
+        assert_eq!("/tmp/otlp.sock", parse_socket("/tmp/otlp.sock".to_string()));
+    }
+
+    #[test]
+    fn test_parse_or() {
+        assert_eq!(32, parse_or("INSPECTOR_MAX_REQUEST_SIZE_MIB", Some("32".to_string()), 20));
+        assert_eq!(20, parse_or("INSPECTOR_MAX_REQUEST_SIZE_MIB", Some("many".to_string()), 20));
+        assert_eq!(20, parse_or("INSPECTOR_MAX_REQUEST_SIZE_MIB", None, 20));
+        assert!(parse_or("INSPECTOR_SINGLE_PORT", Some("true".to_string()), false));
+    }
+}
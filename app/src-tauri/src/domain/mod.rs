@@ -16,6 +16,16 @@ pub fn bytes_to_hex(bytes: &[u8]) -> String {
     hex_string
 }
 
+/// Parses a hex string like the ones from `bytes_to_hex`, returns None when it is not valid hex.
+pub fn hex_to_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
 pub fn any_value_to_string(value: &AnyValue) -> String {
     match &value.value {
         Some(any_value::Value::StringValue(s)) => s.clone(),
@@ -0,0 +1,176 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use futures::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use crate::app_state::AppState;
+use crate::subscription_manager::{topic_matches, topic_root, ClientId, SubscriptionManager, Topic, TopicMessage};
+use crate::telemetry_store::Sequence;
+
+const ALL_TOPICS: &str = "*";
+
+#[derive(Deserialize)]
+pub struct StreamParams {
+    topics: Option<String>,
+}
+
+/// Server-Sent Events alternative to the websocket for clients behind proxies which break websocket upgrades.
+/// Every event carries the sequence number as id, so a reconnecting client gets what it missed from the stored history.
+pub async fn event_stream_handler(State(state): State<AppState>,
+                                  Query(params): Query<StreamParams>,
+                                  headers: HeaderMap) -> impl IntoResponse {
+    let topics = parse_topics(params.topics.as_deref());
+    let last_event_id = headers.get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<Sequence>().ok());
+
+    // subscribe before reading the history, so nothing published in between is lost.
+    // a single subscription keeps the events in sequence order, the requested topics are filtered here.
+    let client_id = Uuid::now_v7().to_string();
+    let subscription = covering_pattern(&topics);
+    let receiver = state.subscription_manager.write().await.subscribe(subscription.clone(), client_id.clone());
+    let guard = SubscriptionGuard {
+        client_id,
+        topic: subscription,
+        subscription_manager: state.subscription_manager.clone(),
+    };
+
+    let history = match last_event_id {
+        Some(after) => {
+            let store = state.telemetry_store.read().await;
+            let mut messages = topics.iter()
+                .flat_map(|topic| store.query(topic, Some(after), usize::MAX))
+                .collect::<Vec<TopicMessage>>();
+            messages.sort_by_key(TopicMessage::seq);
+            messages.dedup_by_key(|message| message.seq());
+            messages
+        },
+        None => Vec::new(),
+    };
+    let last_sequence = history.last().map(TopicMessage::seq).or(last_event_id).unwrap_or_default();
+
+    let events = stream::iter(history)
+        .chain(live_messages(receiver, topics, last_sequence, guard))
+        .map(|message| Ok::<Event, Infallible>(to_event(&message)));
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+fn live_messages(receiver: Receiver<TopicMessage>,
+                 topics: Vec<Topic>,
+                 last_sequence: Sequence,
+                 guard: SubscriptionGuard) -> impl Stream<Item = TopicMessage> {
+    stream::unfold((receiver, topics, guard), move |(mut receiver, topics, guard)| async move {
+        loop {
+            match receiver.recv().await {
+                // skip what was already sent from the history.
+                Ok(message) if message.seq() != 0 && message.seq() <= last_sequence => continue,
+                Ok(message) if topics.iter().any(|topic| topic_matches(topic, message.topic())) => {
+                    return Some((message, (receiver, topics, guard)));
+                },
+                Ok(_) => continue,
+                Err(RecvError::Lagged(count)) => {
+                    println!("Event stream {} skipped {} messages.", guard.client_id, count);
+                    continue;
+                },
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+fn to_event(message: &TopicMessage) -> Event {
+    let json = serde_json::to_string(message).expect("Unable to serialize event.");
+    Event::default()
+        .id(message.seq().to_string())
+        .event(topic_root(message.topic()))
+        .data(json)
+}
+
+fn parse_topics(topics: Option<&str>) -> Vec<Topic> {
+    let topics = topics.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|topic| !topic.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<Topic>>();
+    if topics.is_empty() {
+        vec![ALL_TOPICS.to_string()]
+    } else {
+        topics
+    }
+}
+
+// the longest common prefix of the topics, which is a pattern matching all of them.
+fn covering_pattern(topics: &[Topic]) -> Topic {
+    let mut segments: Option<Vec<&str>> = None;
+    for topic in topics {
+        let topic_segments = topic.split('/').collect::<Vec<&str>>();
+        segments = Some(match segments {
+            None => topic_segments,
+            Some(current) => current.into_iter()
+                .zip(topic_segments)
+                .take_while(|(left, right)| left == right)
+                .map(|(left, _)| left)
+                .collect(),
+        });
+    }
+    match segments {
+        Some(segments) if !segments.is_empty() => segments.join("/"),
+        _ => ALL_TOPICS.to_string(),
+    }
+}
+
+// removes the subscription when the client goes away and the stream is dropped.
+struct SubscriptionGuard {
+    client_id: ClientId,
+    topic: Topic,
+    subscription_manager: Arc<RwLock<SubscriptionManager>>,
+}
+
+impl Drop for SubscriptionGuard {
+    fn drop(&mut self) {
+        let client_id = self.client_id.clone();
+        let topic = self.topic.clone();
+        let subscription_manager = self.subscription_manager.clone();
+        tokio::spawn(async move {
+            subscription_manager.write().await.unsubscribe(&client_id, &topic);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_topics() {
+        assert_eq!(vec!["*".to_string()], parse_topics(None));
+        assert_eq!(vec!["*".to_string()], parse_topics(Some(" , ")));
+        assert_eq!(vec!["logs/checkout/*".to_string(), "traces".to_string()], parse_topics(Some("logs/checkout/*, traces")));
+    }
+
+    #[test]
+    fn test_covering_pattern() {
+        assert_eq!("logs/checkout/*", covering_pattern(&["logs/checkout/*".to_string()]));
+        assert_eq!("logs", covering_pattern(&["logs/checkout/*".to_string(), "logs/*/error".to_string()]));
+        assert_eq!("traces/checkout", covering_pattern(&["traces/checkout".to_string(), "traces/checkout".to_string()]));
+        assert_eq!("*", covering_pattern(&["logs".to_string(), "traces".to_string()]));
+        assert_eq!("*", covering_pattern(&[]));
+    }
+
+    #[test]
+    fn test_to_event() {
+        let message = TopicMessage::new("logs/checkout/error", "test").with_seq(42);
+        let event = format!("{:?}", to_event(&message));
+        assert!(event.contains("id: 42"), "{}", event);
+        assert!(event.contains("event: logs"), "{}", event);
+    }
+}
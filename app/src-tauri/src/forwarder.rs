@@ -0,0 +1,213 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use prost::Message as ProstMessage;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tonic::Code;
+use crate::config::{ForwardConfig, ForwardProtocol};
+use crate::opentelemetry::proto::collector::logs::v1::ExportLogsServiceRequest;
+use crate::opentelemetry::proto::collector::logs::v1::logs_service_client::LogsServiceClient;
+use crate::opentelemetry::proto::collector::metrics::v1::ExportMetricsServiceRequest;
+use crate::opentelemetry::proto::collector::metrics::v1::metrics_service_client::MetricsServiceClient;
+use crate::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+use crate::opentelemetry::proto::collector::trace::v1::trace_service_client::TraceServiceClient;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub enum ExportRequest {
+    Logs(ExportLogsServiceRequest),
+    Traces(ExportTraceServiceRequest),
+    Metrics(ExportMetricsServiceRequest),
+}
+
+impl ExportRequest {
+    fn signal(&self) -> &'static str {
+        match self {
+            ExportRequest::Logs(_) => "logs",
+            ExportRequest::Traces(_) => "traces",
+            ExportRequest::Metrics(_) => "metrics",
+        }
+    }
+}
+
+/// Sends a copy of the received export requests to an upstream OTLP receiver.
+/// Requests wait in a bounded queue and a single worker sends them with retries.
+pub struct Forwarder {
+    queue: mpsc::Sender<ExportRequest>,
+    // the worker is started with the first request, so it runs on the servers runtime.
+    worker: Mutex<Option<ForwardWorker>>,
+}
+
+impl Forwarder {
+    pub fn new(config: ForwardConfig) -> Self {
+        let (queue, receiver) = mpsc::channel(config.queue_size.max(1));
+        println!("Forwarding telemetry to {} ({:?})", config.endpoint, config.protocol);
+        Self {
+            queue,
+            worker: Mutex::new(Some(ForwardWorker { config, receiver })),
+        }
+    }
+
+    pub fn forward(&self, request: ExportRequest) {
+        if let Some(worker) = self.worker.lock().expect("Forward worker lock is poisoned.").take() {
+            tokio::spawn(worker.run());
+        }
+        match self.queue.try_send(request) {
+            Ok(_) => {},
+            Err(TrySendError::Full(request)) => println!("Forward queue is full, dropping {} export request.", request.signal()),
+            Err(TrySendError::Closed(_)) => println!("Forward worker stopped, dropping export request."),
+        }
+    }
+}
+
+struct ForwardWorker {
+    config: ForwardConfig,
+    receiver: mpsc::Receiver<ExportRequest>,
+}
+
+impl ForwardWorker {
+    async fn run(mut self) {
+        let upstream = match Upstream::new(&self.config) {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                println!("Unable to create upstream client for {}: {}", self.config.endpoint, e);
+                return;
+            }
+        };
+        while let Some(request) = self.receiver.recv().await {
+            let mut backoff = INITIAL_BACKOFF;
+            let mut attempt = 0;
+            loop {
+                match upstream.export(&request).await {
+                    Ok(_) => break,
+                    Err(e) if e.retryable && attempt < self.config.max_retries => {
+                        attempt += 1;
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    },
+                    Err(e) => {
+                        println!("Unable to forward {} export request: {}", request.signal(), e.message);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct ForwardError {
+    retryable: bool,
+    message: String,
+}
+
+enum Upstream {
+    Grpc {
+        channel: Channel,
+    },
+    Http {
+        client: reqwest::Client,
+        endpoint: String,
+    },
+}
+
+impl Upstream {
+    fn new(config: &ForwardConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        match config.protocol {
+            ForwardProtocol::Grpc => {
+                let mut endpoint = Endpoint::from_shared(config.endpoint.clone())?.timeout(REQUEST_TIMEOUT);
+                if config.endpoint.starts_with("https://") {
+                    endpoint = endpoint.tls_config(ClientTlsConfig::new().with_webpki_roots())?;
+                }
+                // connect lazily, the upstream may not be running yet.
+                Ok(Upstream::Grpc { channel: endpoint.connect_lazy() })
+            },
+            ForwardProtocol::HttpProtobuf => {
+                let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+                Ok(Upstream::Http { client, endpoint: config.endpoint.trim_end_matches('/').to_string() })
+            }
+        }
+    }
+
+    async fn export(&self, request: &ExportRequest) -> Result<(), ForwardError> {
+        match self {
+            Upstream::Grpc { channel } => {
+                // the clients are cheap to create, they share the channel.
+                let result = match request {
+                    ExportRequest::Logs(r) => LogsServiceClient::new(channel.clone()).export(r.clone()).await.map(|_| ()),
+                    ExportRequest::Traces(r) => TraceServiceClient::new(channel.clone()).export(r.clone()).await.map(|_| ()),
+                    ExportRequest::Metrics(r) => MetricsServiceClient::new(channel.clone()).export(r.clone()).await.map(|_| ()),
+                };
+                result.map_err(|status| ForwardError {
+                    retryable: is_retryable_code(status.code()),
+                    message: status.to_string(),
+                })
+            },
+            Upstream::Http { client, endpoint } => {
+                let body = match request {
+                    ExportRequest::Logs(r) => r.encode_to_vec(),
+                    ExportRequest::Traces(r) => r.encode_to_vec(),
+                    ExportRequest::Metrics(r) => r.encode_to_vec(),
+                };
+                let url = format!("{}/v1/{}", endpoint, request.signal());
+                let response = client.post(url)
+                    .header("content-type", "application/x-protobuf")
+                    .body(body)
+                    .send()
+                    .await
+                    .map_err(|e| ForwardError { retryable: true, message: e.to_string() })?;
+                let status = response.status();
+                if status.is_success() {
+                    Ok(())
+                } else {
+                    Err(ForwardError { retryable: is_retryable_status(status.as_u16()), message: status.to_string() })
+                }
+            }
+        }
+    }
+}
+
+// https://opentelemetry.io/docs/specs/otlp/#failures
+fn is_retryable_code(code: Code) -> bool {
+    matches!(code,
+        Code::Cancelled | Code::DeadlineExceeded | Code::Aborted | Code::OutOfRange |
+        Code::Unavailable | Code::DataLoss | Code::ResourceExhausted)
+}
+
+// https://opentelemetry.io/docs/specs/otlp/#failures-1
+fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 429 | 502 | 503 | 504)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retryable() {
+        assert!(is_retryable_code(Code::Unavailable));
+        assert!(!is_retryable_code(Code::InvalidArgument));
+        assert!(is_retryable_status(503));
+        assert!(!is_retryable_status(400));
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_requests() {
+        let forwarder = Forwarder::new(ForwardConfig {
+            endpoint: "http://localhost:1".to_string(),
+            protocol: ForwardProtocol::HttpProtobuf,
+            queue_size: 1,
+            max_retries: 0,
+        });
+        // keep the worker from consuming the queue.
+        let worker = forwarder.worker.lock().unwrap().take();
+        forwarder.forward(ExportRequest::Logs(ExportLogsServiceRequest::default()));
+        forwarder.forward(ExportRequest::Logs(ExportLogsServiceRequest::default()));
+
+        let mut receiver = worker.unwrap().receiver;
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -1,12 +1,40 @@
 use tonic::{Request, Response, Status};
+use tonic::service::interceptor::InterceptedService;
+use std::convert::Infallible;
+use tonic::body::Body;
+use tonic::service::Routes;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tonic::transport::server::TcpIncoming;
+use tonic_health::pb::health_server::HealthServer;
+use tonic_health::server::{HealthReporter, HealthService};
+use tonic_health::ServingStatus;
+use tonic_web::GrpcWebLayer;
+use tower::util::{option_layer, BoxCloneSyncService};
+use tower::ServiceBuilder;
+use tower_http::cors::CorsLayer;
 use crate::opentelemetry::proto::collector::logs::v1::logs_service_server::{LogsService, LogsServiceServer};
 use crate::opentelemetry::proto::collector::logs::v1::{ExportLogsServiceRequest, ExportLogsServiceResponse};
 use crate::opentelemetry::proto::collector::trace::v1::trace_service_server::{TraceService, TraceServiceServer};
 use crate::opentelemetry::proto::collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse};
 use crate::app_state::AppState;
+use crate::auth::IngestInterceptor;
+use crate::health::set_otlp_status;
 use crate::opentelemetry::proto::collector::metrics::v1::{ExportMetricsServiceRequest, ExportMetricsServiceResponse};
 use crate::opentelemetry::proto::collector::metrics::v1::metrics_service_server::{MetricsService, MetricsServiceServer};
+use crate::opentelemetry::FILE_DESCRIPTOR_SET;
+use crate::receivers::jaeger;
 use crate::server::shutdown_signal;
+#[cfg(unix)]
+use crate::server::bind_unix_socket;
+#[cfg(unix)]
+use tokio_stream::wrappers::UnixListenerStream;
+use crate::tls;
+use crate::web_server::cors_layer;
+
+// the browsers only let the grpc-web clients read the status of a call with these.
+const GRPC_WEB_EXPOSED_HEADERS: [&str; 3] = ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+
+pub type GrpcService = BoxCloneSyncService<axum::http::Request<Body>, axum::http::Response<Body>, Infallible>;
 
 pub struct GrpcLogsService {
     state: AppState,
@@ -18,6 +46,7 @@ impl LogsService for GrpcLogsService {
         &self,
         request: Request<ExportLogsServiceRequest>,
     ) -> Result<Response<ExportLogsServiceResponse>, Status> {
+        let _permit = self.state.ingest_limiter.acquire().await?;
         let inner = request.into_inner();
         self.state.request_processor.process_logs(inner).await;
         Ok(Response::new(ExportLogsServiceResponse::default()))
@@ -34,6 +63,7 @@ impl TraceService for GrpcTraceService {
         &self,
         request: Request<ExportTraceServiceRequest>,
     ) -> Result<Response<ExportTraceServiceResponse>, Status> {
+        let _permit = self.state.ingest_limiter.acquire().await?;
         let inner = request.into_inner();
         self.state.request_processor.process_traces(inner).await;
         Ok(Response::new(ExportTraceServiceResponse::default()))
@@ -50,6 +80,7 @@ impl MetricsService for GrpcMetricsService {
         &self,
         request: Request<ExportMetricsServiceRequest>,
     ) -> Result<Response<ExportMetricsServiceResponse>, Status> {
+        let _permit = self.state.ingest_limiter.acquire().await?;
         let inner = request.into_inner();
         self.state.request_processor.process_metrics(inner).await;
         Ok(Response::new(ExportMetricsServiceResponse::default()))
@@ -58,18 +89,98 @@ impl MetricsService for GrpcMetricsService {
 
 pub async fn init_grpc(state: AppState) -> Result<(), Box<dyn std::error::Error>> {
     let addr = "[::]:4317".parse()?;
-    println!("gRPC server listening on {}", addr);
+    // bound before the health status says serving, a port in use fails here instead.
+    let incoming = TcpIncoming::bind(addr)?.with_nodelay(Some(true));
+    // grpc-web comes over http/1.1 from the browsers.
+    let mut server = tonic::transport::Server::builder().accept_http1(true);
+    if let Some(tls) = &state.config.tls {
+        let identity = tls::load_identity(tls)?;
+        let mut tls_config = ServerTlsConfig::new().identity(Identity::from_pem(identity.cert_pem, identity.key_pem));
+        if let Some(client_ca_pem) = identity.client_ca_pem {
+            tls_config = tls_config.client_ca_root(Certificate::from_pem(client_ca_pem));
+        }
+        server = server.tls_config(tls_config)?;
+        println!("gRPC server listening on {} with TLS", addr);
+    } else {
+        println!("gRPC server listening on {}", addr);
+    }
+
+    let grpc_socket = state.config.grpc_socket.clone();
+    let health_reporter = state.health_reporter.clone();
+    let shutdown = || otlp_shutdown(health_reporter.clone());
+    let router = server
+        .layer(option_layer(grpc_web_cors_layer(&state)))
+        .layer(GrpcWebLayer::new())
+        .add_routes(grpc_routes(state)?);
+
+    #[cfg(unix)]
+    {
+        if let Some(path) = grpc_socket {
+            let unix_incoming = UnixListenerStream::new(bind_unix_socket(&path)?);
+            println!("gRPC server listening on unix://{}", path);
+            set_otlp_status(&health_reporter, ServingStatus::Serving).await;
+            tokio::try_join!(
+                router.clone().serve_with_incoming_shutdown(incoming, shutdown()),
+                router.serve_with_incoming_shutdown(unix_incoming, shutdown()),
+            )?;
+            return Ok(());
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        if grpc_socket.is_some() {
+            println!("Unix domain sockets are not supported on this platform.");
+        }
+    }
+    set_otlp_status(&health_reporter, ServingStatus::Serving).await;
+    router.serve_with_incoming_shutdown(incoming, shutdown()).await?;
+
+    Ok(())
+}
+
+/// The grpc services with the grpc-web and CORS layers, which the http port calls in the single port mode.
+pub fn multiplexed_grpc_service(state: AppState) -> Result<GrpcService, tonic_reflection::server::Error> {
+    let service = ServiceBuilder::new()
+        .layer(option_layer(grpc_web_cors_layer(&state)))
+        .layer(GrpcWebLayer::new())
+        .service(grpc_routes(state)?);
+    Ok(GrpcService::new(service))
+}
 
+/// Waits for the shutdown signal. The health checks go to not serving first, while the requests in flight finish.
+pub async fn otlp_shutdown(health_reporter: HealthReporter) {
+    shutdown_signal().await;
+    set_otlp_status(&health_reporter, ServingStatus::NotServing).await;
+}
+
+// the OTLP, Jaeger, health and reflection services.
+fn grpc_routes(state: AppState) -> Result<Routes, tonic_reflection::server::Error> {
     let logs_service = GrpcLogsService { state: state.clone() };
     let trace_service = GrpcTraceService { state: state.clone() };
-    let metrics_service = GrpcMetricsService { state };
+    let metrics_service = GrpcMetricsService { state: state.clone() };
+    let interceptor = IngestInterceptor::new(state.config.clone());
+    let max_message_size = state.config.limits.max_request_size;
+    let health_service = HealthServer::new(HealthService::from_health_reporter(state.health_reporter.clone()));
+    // grpcurl and the other tools describe the OTLP and health services with the reflection, v1alpha for the older ones.
+    let reflection = || tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET);
+    let reflection_service = reflection().build_v1()?;
+    let reflection_v1alpha_service = reflection().build_v1alpha()?;
 
-    tonic::transport::Server::builder()
-        .add_service(LogsServiceServer::new(logs_service))
-        .add_service(TraceServiceServer::new(trace_service))
-        .add_service(MetricsServiceServer::new(metrics_service))
-        .serve_with_shutdown(addr, shutdown_signal())
-        .await?;
+    let mut routes = Routes::builder();
+    routes
+        .add_service(InterceptedService::new(LogsServiceServer::new(logs_service).max_decoding_message_size(max_message_size), interceptor.clone()))
+        .add_service(InterceptedService::new(TraceServiceServer::new(trace_service).max_decoding_message_size(max_message_size), interceptor.clone()))
+        .add_service(InterceptedService::new(MetricsServiceServer::new(metrics_service).max_decoding_message_size(max_message_size), interceptor.clone()))
+        .add_service(InterceptedService::new(jaeger::collector_service(state).max_decoding_message_size(max_message_size), interceptor))
+        .add_service(health_service)
+        .add_service(reflection_service)
+        .add_service(reflection_v1alpha_service);
+    Ok(routes.routes())
+}
 
-    Ok(())
+fn grpc_web_cors_layer(state: &AppState) -> Option<CorsLayer> {
+    state.config.cors.as_ref()
+        .map(|cors| cors_layer(cors).expose_headers(GRPC_WEB_EXPOSED_HEADERS.map(axum::http::HeaderName::from_static)))
 }
@@ -0,0 +1,74 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use tonic::server::NamedService;
+use tonic_health::pb::health_server::Health;
+use tonic_health::pb::health_check_response::ServingStatus as CheckedStatus;
+use tonic_health::pb::HealthCheckRequest;
+use tonic_health::server::{HealthReporter, HealthService};
+use tonic_health::ServingStatus;
+use crate::app_state::AppState;
+use crate::grpc_server::{GrpcLogsService, GrpcMetricsService, GrpcTraceService};
+use crate::opentelemetry::proto::collector::logs::v1::logs_service_server::LogsServiceServer;
+use crate::opentelemetry::proto::collector::metrics::v1::metrics_service_server::MetricsServiceServer;
+use crate::opentelemetry::proto::collector::trace::v1::trace_service_server::TraceServiceServer;
+
+// the services which the health checks report, the empty name is the whole server.
+const OTLP_SERVICES: [&str; 3] = [
+    LogsServiceServer::<GrpcLogsService>::NAME,
+    TraceServiceServer::<GrpcTraceService>::NAME,
+    MetricsServiceServer::<GrpcMetricsService>::NAME,
+];
+const SERVER: &str = "";
+
+/// Reports the OTLP grpc services and the whole server as serving or not.
+pub async fn set_otlp_status(reporter: &HealthReporter, status: ServingStatus) {
+    for service in OTLP_SERVICES.iter().chain([&SERVER]) {
+        reporter.set_service_status(service, status).await;
+    }
+}
+
+/// The liveness probe, the http server answers.
+pub async fn healthz_handler() -> &'static str {
+    "ok"
+}
+
+/// The readiness probe, the OTLP grpc services are serving. Answers with the status of each one.
+pub async fn readyz_handler(State(state): State<AppState>) -> Response {
+    let health = HealthService::from_health_reporter(state.health_reporter.clone());
+    let mut ready = true;
+    let mut services = serde_json::Map::new();
+    for service in OTLP_SERVICES {
+        let request = tonic::Request::new(HealthCheckRequest { service: service.to_string() });
+        // the services are unknown until the grpc server starts.
+        let status = health.check(request).await
+            .map(|response| response.into_inner().status())
+            .unwrap_or(CheckedStatus::ServiceUnknown);
+        ready &= status == CheckedStatus::Serving;
+        services.insert(service.to_string(), status.as_str_name().into());
+    }
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(services)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn check(reporter: &HealthReporter, service: &str) -> CheckedStatus {
+        let request = tonic::Request::new(HealthCheckRequest { service: service.to_string() });
+        HealthService::from_health_reporter(reporter.clone()).check(request).await.unwrap().into_inner().status()
+    }
+
+    #[tokio::test]
+    async fn test_otlp_status() {
+        let reporter = HealthReporter::new();
+        set_otlp_status(&reporter, ServingStatus::Serving).await;
+        assert_eq!(CheckedStatus::Serving, check(&reporter, "opentelemetry.proto.collector.trace.v1.TraceService").await);
+        assert_eq!(CheckedStatus::Serving, check(&reporter, SERVER).await);
+
+        set_otlp_status(&reporter, ServingStatus::NotServing).await;
+        assert_eq!(CheckedStatus::NotServing, check(&reporter, "opentelemetry.proto.collector.logs.v1.LogsService").await);
+    }
+}
@@ -7,25 +7,51 @@ mod app_state;
 mod grpc_server;
 mod request_processor;
 mod server;
+mod message_encoding;
+mod config;
+mod telemetry_store;
+mod pause_buffer;
+mod event_stream;
+mod forwarder;
+mod receivers;
+mod metrics_exposition;
+mod tls;
+mod auth;
+mod limits;
+mod health;
+mod ui_assets;
+#[cfg(feature = "tauri")]
+mod remote_ui;
 
 use std::sync::{Arc};
 #[cfg(feature = "tauri")]
 use tauri::{Emitter, Manager};
 use tokio::sync::{RwLock};
+use tonic_health::server::HealthReporter;
 use crate::app_state::AppState;
+use crate::config::Config;
+use crate::forwarder::Forwarder;
 use crate::grpc_server::init_grpc;
+use crate::limits::IngestLimiter;
+use crate::metrics_exposition::MetricsExposition;
+use crate::receivers::init_receivers;
 use crate::request_processor::RequestProcessor;
 use crate::subscription_manager::{SubscriptionManager};
+use crate::telemetry_store::TelemetryStore;
 use crate::web_server::init_axum;
 
 pub async fn axum_main() -> Result<(), &'static str> {
+    let _ = rustls::crypto::ring::default_provider().install_default();
     println!("Starting axum server");
-    let app_state = create_state();
+    let app_state = create_state(Config::from_env());
+    // the http port serves the grpc services as well in the single port mode.
+    let single_port = app_state.config.single_port;
+    tokio::spawn(init_receivers(app_state.clone()));
     tokio::select! {
         res0 = init_axum(app_state.clone()) => {
             res0.or(Err("failed to start axum server"))
         },
-        res1 = init_grpc(app_state) => {
+        res1 = init_grpc(app_state), if !single_port => {
             res1.or(Err("failed to start grpc server"))
         }
     }
@@ -35,23 +61,35 @@ pub async fn axum_main() -> Result<(), &'static str> {
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn tauri_main() {
     let _ = rustls::crypto::ring::default_provider().install_default();
+    let config = remote_ui::desktop_config();
+    if config.serve_ui {
+        println!("The UI is served to other browsers on {}", remote_ui::ui_url(&config));
+    }
+    let inspector = tauri::plugin::Builder::new("inspector")
+        .js_init_script(remote_ui::init_script(&config))
+        .build();
 
     tauri::Builder::default()
-        .setup(|app| {
-            let app_state = create_state();
+        .plugin(inspector)
+        .setup(move |app| {
+            let app_state = create_state(config);
             app.manage(app_state.clone());
             #[cfg(desktop)]
             {
                 app.emit("agent-started", ()).unwrap();
 
+                tauri::async_runtime::spawn(init_receivers(app_state.clone()));
+
                 let web_app_state = app_state.clone();
                 tauri::async_runtime::spawn(async move {
                     init_axum(web_app_state).await.expect("failed to start axum server");
                 });
 
-                tauri::async_runtime::spawn(async move {
-                    init_grpc(app_state).await.expect("failed to start grpc server");
-                });
+                if !app_state.config.single_port {
+                    tauri::async_runtime::spawn(async move {
+                        init_grpc(app_state).await.expect("failed to start grpc server");
+                    });
+                }
             }
             Ok(())
         })
@@ -59,11 +97,21 @@ pub fn tauri_main() {
         .expect("error while running tauri application");
 }
 
-fn create_state() -> AppState {
+fn create_state(config: Config) -> AppState {
+    let config = Arc::new(config);
     let subscription_manager = Arc::new(RwLock::new(SubscriptionManager::new()));
-    let request_processor = Arc::new(RequestProcessor::new(subscription_manager.clone()));
+    let telemetry_store = Arc::new(RwLock::new(TelemetryStore::new(config.history_size)));
+    let forwarder = config.forward.clone().map(Forwarder::new);
+    let metrics_exposition = Arc::new(RwLock::new(MetricsExposition::new()));
+    let request_processor = Arc::new(RequestProcessor::new(subscription_manager.clone(), telemetry_store.clone(), metrics_exposition.clone(), forwarder));
+    let ingest_limiter = Arc::new(IngestLimiter::new(config.limits.max_concurrent_requests));
     AppState {
+        config,
         subscription_manager,
+        telemetry_store,
         request_processor,
+        metrics_exposition,
+        ingest_limiter,
+        health_reporter: HealthReporter::new(),
     }
 }
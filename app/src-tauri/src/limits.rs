@@ -0,0 +1,154 @@
+use std::sync::Arc;
+use std::time::Duration;
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http_body_util::LengthLimitError;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+use crate::app_state::AppState;
+
+// how long an export request waits for its turn before the inspector says it is overloaded.
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(2);
+// when the senders should try again, for both kinds of overload.
+const RETRY_AFTER: Duration = Duration::from_secs(2);
+
+/// Why an export request was refused, both are retryable for the OTLP exporters.
+#[derive(Debug, PartialEq)]
+pub enum Overload {
+    /// All the requests which may wait are already waiting.
+    TooManyRequests,
+    /// The request waited too long, the store and the subscribers don't keep up.
+    Busy,
+}
+
+impl IntoResponse for Overload {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Overload::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            Overload::Busy => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, [(header::RETRY_AFTER, RETRY_AFTER.as_secs().to_string())], "The inspector is overloaded, retry later").into_response()
+    }
+}
+
+// the grpc exporters only retry RESOURCE_EXHAUSTED when the status has a RetryInfo.
+impl From<Overload> for Status {
+    fn from(overload: Overload) -> Self {
+        let code = match overload {
+            Overload::TooManyRequests => Code::ResourceExhausted,
+            Overload::Busy => Code::Unavailable,
+        };
+        Status::with_error_details(code, "The inspector is overloaded, retry later", ErrorDetails::with_retry_info(Some(RETRY_AFTER)))
+    }
+}
+
+/// Limits how many export requests are processed at the same time, over all the receivers which can push back.
+pub struct IngestLimiter {
+    permits: Arc<Semaphore>,
+    queue: Semaphore,
+    queue_timeout: Duration,
+}
+
+impl IngestLimiter {
+    pub fn new(max_concurrent_requests: usize) -> Self {
+        Self::with_queue_timeout(max_concurrent_requests, QUEUE_TIMEOUT)
+    }
+
+    fn with_queue_timeout(max_concurrent_requests: usize, queue_timeout: Duration) -> Self {
+        IngestLimiter {
+            permits: Arc::new(Semaphore::new(max_concurrent_requests)),
+            queue: Semaphore::new(max_concurrent_requests),
+            queue_timeout,
+        }
+    }
+
+    /// Waits for the turn of a request, which lasts as long as the permit is kept.
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, Overload> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+        let _queued = self.queue.try_acquire().map_err(|_| Overload::TooManyRequests)?;
+        match tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned()).await {
+            Ok(Ok(permit)) => Ok(permit),
+            _ => Err(Overload::Busy),
+        }
+    }
+}
+
+/// Keeps a permit of the limiter while an http export request is processed.
+pub async fn limit_ingest(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    match state.ingest_limiter.acquire().await {
+        Ok(_permit) => next.run(request).await,
+        Err(overload) => overload.into_response(),
+    }
+}
+
+/// Reads the whole request body, up to the configured size.
+pub async fn read_body(body: Body, max_size: usize) -> Result<Bytes, Response> {
+    axum::body::to_bytes(body, max_size).await.map_err(|e| {
+        if e.into_inner().is::<LengthLimitError>() {
+            payload_too_large(max_size)
+        } else {
+            (StatusCode::BAD_REQUEST, "Failed to read request body").into_response()
+        }
+    })
+}
+
+/// Whether the snappy block would decompress to more than the configured size.
+pub fn exceeds_snappy_size(body: &[u8], max_size: usize) -> bool {
+    snap::raw::decompress_len(body).is_ok_and(|length| length > max_size)
+}
+
+/// The 413 response, which the exporters don't retry.
+pub fn payload_too_large(max_size: usize) -> Response {
+    (StatusCode::PAYLOAD_TOO_LARGE, format!("The request body is larger than {} bytes", max_size)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_acquire() {
+        let limiter = IngestLimiter::with_queue_timeout(1, Duration::from_millis(10));
+        let permit = limiter.acquire().await.unwrap();
+        // the second request waits in the queue, the third one finds it full.
+        let (queued, refused) = tokio::join!(limiter.acquire(), async {
+            tokio::task::yield_now().await;
+            limiter.acquire().await
+        });
+        assert_eq!(Some(Overload::Busy), queued.err());
+        assert_eq!(Some(Overload::TooManyRequests), refused.err());
+
+        drop(permit);
+        assert!(limiter.acquire().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_overload_responses() {
+        let response = Overload::TooManyRequests.into_response();
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, response.status());
+        assert_eq!("2", response.headers()[header::RETRY_AFTER]);
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, Overload::Busy.into_response().status());
+
+        let status = Status::from(Overload::TooManyRequests);
+        assert_eq!(Code::ResourceExhausted, status.code());
+        assert_eq!(Some(RETRY_AFTER), status.get_details_retry_info().and_then(|info| info.retry_delay));
+        assert_eq!(Code::Unavailable, Status::from(Overload::Busy).code());
+    }
+
+    #[tokio::test]
+    async fn test_read_body() {
+        assert_eq!(Bytes::from("small"), read_body(Body::from("small"), 5).await.unwrap());
+        let response = read_body(Body::from("too large"), 5).await.unwrap_err();
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, response.status());
+
+        let compressed = snap::raw::Encoder::new().compress_vec(&[0; 100]).unwrap();
+        assert!(!exceeds_snappy_size(&compressed, 100));
+        assert!(exceeds_snappy_size(&compressed, 99));
+    }
+}
@@ -0,0 +1,109 @@
+use axum::body::Bytes;
+use axum::extract::ws::{Message, Utf8Bytes};
+use serde::Serialize;
+
+pub type EncodingError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Wire format of the messages sent to a websocket client.
+/// The client picks one through the `Sec-WebSocket-Protocol` header when it connects,
+/// all of them carry the same schema as the JSON messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MessageEncoding {
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl MessageEncoding {
+    // ordered by preference, when the client offers more than one protocol the first match wins.
+    pub const PROTOCOLS: [&'static str; 3] = ["msgpack", "cbor", "json"];
+
+    pub fn from_protocol(protocol: &str) -> Option<Self> {
+        match protocol {
+            "json" => Some(MessageEncoding::Json),
+            "msgpack" => Some(MessageEncoding::MessagePack),
+            "cbor" => Some(MessageEncoding::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Json goes out as text frames, MessagePack and CBOR as binary frames.
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Message, EncodingError> {
+        match self {
+            MessageEncoding::Json => {
+                let json = serde_json::to_string(value)?;
+                Ok(Message::Text(Utf8Bytes::from(json)))
+            },
+            MessageEncoding::MessagePack => {
+                // use named fields, so the payload keeps the same shape as the json one.
+                let bytes = rmp_serde::to_vec_named(value)?;
+                Ok(Message::Binary(Bytes::from(bytes)))
+            },
+            MessageEncoding::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)?;
+                Ok(Message::Binary(Bytes::from(bytes)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use chrono::{TimeZone, Utc};
+    use crate::domain::logs::{LogDto, Severity};
+    use crate::domain::resource::ResourceInfo;
+    use crate::subscription_manager::TopicMessage;
+    use super::*;
+
+    fn create_message() -> TopicMessage {
+        TopicMessage::from(LogDto {
+            timestamp: Utc.with_ymd_and_hms(2025, 1, 12, 14, 23, 20).unwrap(),
+            time_unix_nano: "1641996200000000000".to_string(),
+            message: "test".to_string(),
+            scope: "TestScope".to_string(),
+            severity: Severity::Error,
+            tags: HashMap::from([("key".to_string(), "value".to_string())]),
+            resource: ResourceInfo::default(),
+            event_name: None,
+            span_id: None,
+            trace_id: None
+        })
+    }
+
+    #[test]
+    fn test_from_protocol() {
+        assert_eq!(MessageEncoding::from_protocol("json"), Some(MessageEncoding::Json));
+        assert_eq!(MessageEncoding::from_protocol("msgpack"), Some(MessageEncoding::MessagePack));
+        assert_eq!(MessageEncoding::from_protocol("cbor"), Some(MessageEncoding::Cbor));
+        assert_eq!(MessageEncoding::from_protocol("xml"), None);
+    }
+
+    #[test]
+    fn test_json_is_text() {
+        let message = create_message();
+        let encoded = MessageEncoding::Json.encode(&message).unwrap();
+        let Message::Text(text) = encoded else { panic!("Expected text message") };
+        assert_eq!(serde_json::to_string(&message).unwrap(), text.as_str());
+    }
+
+    #[test]
+    fn test_msgpack_has_same_schema() {
+        let message = create_message();
+        let encoded = MessageEncoding::MessagePack.encode(&message).unwrap();
+        let Message::Binary(bytes) = encoded else { panic!("Expected binary message") };
+        let decoded: serde_json::Value = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(serde_json::to_value(&message).unwrap(), decoded);
+    }
+
+    #[test]
+    fn test_cbor_has_same_schema() {
+        let message = create_message();
+        let encoded = MessageEncoding::Cbor.encode(&message).unwrap();
+        let Message::Binary(bytes) = encoded else { panic!("Expected binary message") };
+        let decoded: serde_json::Value = ciborium::from_reader(bytes.as_ref()).unwrap();
+        assert_eq!(serde_json::to_value(&message).unwrap(), decoded);
+    }
+}
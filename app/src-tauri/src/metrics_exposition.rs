@@ -0,0 +1,575 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use opentelemetry_semantic_conventions::resource::{SERVICE_INSTANCE_ID, SERVICE_NAME, SERVICE_NAMESPACE};
+use crate::app_state::AppState;
+use crate::domain::any_value_to_string_optional;
+use crate::opentelemetry::proto::collector::metrics::v1::ExportMetricsServiceRequest;
+use crate::opentelemetry::proto::common::v1::{InstrumentationScope, KeyValue};
+use crate::opentelemetry::proto::metrics::v1::{metric, number_data_point, AggregationTemporality, DataPointFlags, HistogramDataPoint, Metric, NumberDataPoint};
+use crate::opentelemetry::proto::resource::v1::Resource;
+use crate::ui_assets::index_page;
+
+const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+const OPEN_METRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const TARGET_INFO: &str = "target_info";
+const SCOPE_NAME_LABEL: &str = "otel_scope_name";
+const SCOPE_VERSION_LABEL: &str = "otel_scope_version";
+
+/// The label names and values of a series, sorted by name.
+type Labels = Vec<(String, String)>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FamilyType {
+    Counter,
+    Gauge,
+    Histogram,
+    Summary,
+    Info,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(f64),
+    Histogram { bounds: Vec<f64>, bucket_counts: Vec<u64>, sum: f64, count: u64 },
+    Summary { quantiles: Vec<(f64, f64)>, sum: f64, count: u64 },
+}
+
+#[derive(Debug)]
+struct Family {
+    family_type: FamilyType,
+    help: String,
+    unit: String,
+    series: BTreeMap<Labels, Value>,
+}
+
+/// The latest value of every received metric series, rendered in the Prometheus exposition formats the way
+/// the OpenTelemetry Prometheus exporter would: delta sums and histograms are accumulated into cumulative ones,
+/// exponential histograms are not part of the formats and are dropped.
+// https://opentelemetry.io/docs/specs/otel/compatibility/prometheus_and_openmetrics/#otlp-metric-points-to-prometheus
+#[derive(Debug, Default)]
+pub struct MetricsExposition {
+    families: BTreeMap<String, Family>,
+}
+
+impl MetricsExposition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, request: &ExportMetricsServiceRequest) {
+        for resource_metrics in &request.resource_metrics {
+            let resource = resource_metrics.resource.as_ref();
+            let resource_labels = resource_labels(resource);
+            self.update_target_info(resource, &resource_labels);
+            for scope_metrics in &resource_metrics.scope_metrics {
+                let mut common_labels = resource_labels.clone();
+                common_labels.extend(scope_labels(scope_metrics.scope.as_ref()));
+                for metric in &scope_metrics.metrics {
+                    self.update_metric(metric, &common_labels);
+                }
+            }
+        }
+    }
+
+    fn update_target_info(&mut self, resource: Option<&Resource>, resource_labels: &[(String, String)]) {
+        let attributes = resource.map(|r| r.attributes.as_slice()).unwrap_or_default()
+            .iter()
+            .filter(|a| ![SERVICE_NAME, SERVICE_NAMESPACE, SERVICE_INSTANCE_ID].contains(&a.key.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        if attributes.is_empty() {
+            return;
+        }
+        let labels = labels(&attributes, resource_labels);
+        self.set(TARGET_INFO, FamilyType::Info, "Target metadata", "", labels, Value::Number(1.0), false);
+    }
+
+    fn update_metric(&mut self, metric: &Metric, common_labels: &[(String, String)]) {
+        let Some(data) = &metric.data else {
+            return;
+        };
+        let (family_type, delta) = match data {
+            metric::Data::Gauge(_) => (FamilyType::Gauge, false),
+            metric::Data::Sum(sum) if sum.is_monotonic => (FamilyType::Counter, sum.aggregation_temporality == AggregationTemporality::Delta as i32),
+            // non monotonic sums go up and down like gauges.
+            metric::Data::Sum(_) => (FamilyType::Gauge, false),
+            metric::Data::Histogram(histogram) => (FamilyType::Histogram, histogram.aggregation_temporality == AggregationTemporality::Delta as i32),
+            metric::Data::Summary(_) => (FamilyType::Summary, false),
+            metric::Data::ExponentialHistogram(_) => return,
+        };
+        let unit = unit_suffix(&metric.unit, family_type == FamilyType::Gauge);
+        let name = metric_name(&metric.name, &unit, family_type == FamilyType::Counter);
+
+        let mut points = Vec::new();
+        match data {
+            metric::Data::Gauge(gauge) => points.extend(gauge.data_points.iter().map(number_point)),
+            metric::Data::Sum(sum) => points.extend(sum.data_points.iter().map(number_point)),
+            metric::Data::Histogram(histogram) => points.extend(histogram.data_points.iter().map(histogram_point)),
+            metric::Data::Summary(summary) => points.extend(summary.data_points.iter().map(|point| (
+                point.attributes.as_slice(),
+                point.flags,
+                Value::Summary {
+                    quantiles: point.quantile_values.iter().map(|q| (q.quantile, q.value)).collect(),
+                    sum: point.sum,
+                    count: point.count,
+                },
+            ))),
+            metric::Data::ExponentialHistogram(_) => {},
+        }
+        for (attributes, flags, value) in points {
+            let labels = labels(attributes, common_labels);
+            if flags & DataPointFlags::NoRecordedValueMask as u32 != 0 {
+                // the equivalent of a staleness marker, the series is gone.
+                if let Some(family) = self.families.get_mut(&name) {
+                    family.series.remove(&labels);
+                }
+                continue;
+            }
+            self.set(&name, family_type, &metric.description, &unit, labels, value, delta);
+        }
+        self.families.retain(|_, family| !family.series.is_empty());
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn set(&mut self, name: &str, family_type: FamilyType, help: &str, unit: &str, labels: Labels, value: Value, delta: bool) {
+        let family = self.families.entry(name.to_string()).or_insert_with(|| Family {
+            family_type,
+            help: String::new(),
+            unit: String::new(),
+            series: BTreeMap::new(),
+        });
+        // a name reused with another type replaces the previous family.
+        if family.family_type != family_type {
+            family.family_type = family_type;
+            family.series.clear();
+        }
+        family.help = help.to_string();
+        family.unit = unit.to_string();
+
+        let value = match (delta, family.series.remove(&labels), value) {
+            (true, Some(Value::Number(previous)), Value::Number(value)) => Value::Number(previous + value),
+            (true,
+                Some(Value::Histogram { bounds: previous_bounds, bucket_counts: previous_counts, sum: previous_sum, count: previous_count }),
+                Value::Histogram { bounds, bucket_counts, sum, count })
+                if previous_bounds == bounds && previous_counts.len() == bucket_counts.len() => Value::Histogram {
+                    bounds,
+                    bucket_counts: previous_counts.iter().zip(bucket_counts).map(|(a, b)| a + b).collect(),
+                    sum: previous_sum + sum,
+                    count: previous_count + count,
+                },
+            (_, _, value) => value,
+        };
+        family.series.insert(labels, value);
+    }
+
+    pub fn render(&self, open_metrics: bool) -> String {
+        let mut text = String::new();
+        for (name, family) in &self.families {
+            render_family(&mut text, name, family, open_metrics);
+        }
+        if open_metrics {
+            text.push_str("# EOF\n");
+        }
+        text
+    }
+}
+
+/// Serves the exposition, in OpenMetrics when the scraper asks for it. The UI has a `/metrics` page too,
+/// so a browser asking for html gets the UI when it is served next to the APIs.
+pub async fn metrics_exposition_handler(State(state): State<AppState>, request: Request) -> Response {
+    let headers = request.headers();
+    if state.config.serve_ui && accepts(headers, "text/html") {
+        return index_page(&state.config, request).await;
+    }
+
+    let open_metrics = accepts(headers, "application/openmetrics-text");
+    let text = state.metrics_exposition.read().await.render(open_metrics);
+    let content_type = if open_metrics { OPEN_METRICS_CONTENT_TYPE } else { TEXT_CONTENT_TYPE };
+    ([(header::CONTENT_TYPE, content_type)], text).into_response()
+}
+
+fn accepts(headers: &HeaderMap, media_type: &str) -> bool {
+    headers.get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|accepted| accepted.trim().starts_with(media_type))
+}
+
+fn number_point(point: &NumberDataPoint) -> (&[KeyValue], u32, Value) {
+    let value = match point.value {
+        Some(number_data_point::Value::AsDouble(value)) => value,
+        Some(number_data_point::Value::AsInt(value)) => value as f64,
+        None => 0.0,
+    };
+    (point.attributes.as_slice(), point.flags, Value::Number(value))
+}
+
+fn histogram_point(point: &HistogramDataPoint) -> (&[KeyValue], u32, Value) {
+    (point.attributes.as_slice(), point.flags, Value::Histogram {
+        bounds: point.explicit_bounds.clone(),
+        bucket_counts: point.bucket_counts.clone(),
+        sum: point.sum.unwrap_or_default(),
+        count: point.count,
+    })
+}
+
+// `job` and `instance` identify the target, as the service namespace and name, and the service instance.
+fn resource_labels(resource: Option<&Resource>) -> Labels {
+    let attribute = |key: &str| resource
+        .and_then(|r| r.attributes.iter().find(|a| a.key == key))
+        .map(|a| any_value_to_string_optional(a.value.as_ref()))
+        .unwrap_or_default();
+    let job = match (attribute(SERVICE_NAMESPACE), attribute(SERVICE_NAME)) {
+        (namespace, name) if namespace.is_empty() => name,
+        (namespace, name) => format!("{}/{}", namespace, name),
+    };
+    [("job", job), ("instance", attribute(SERVICE_INSTANCE_ID))].into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+}
+
+fn scope_labels(scope: Option<&InstrumentationScope>) -> Labels {
+    let Some(scope) = scope else {
+        return Vec::new();
+    };
+    [(SCOPE_NAME_LABEL, &scope.name), (SCOPE_VERSION_LABEL, &scope.version)].into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect()
+}
+
+// the common labels win over attributes sanitized to the same name.
+fn labels(attributes: &[KeyValue], common_labels: &[(String, String)]) -> Labels {
+    let mut labels = BTreeMap::new();
+    for attribute in attributes {
+        labels.insert(label_name(&attribute.key), any_value_to_string_optional(attribute.value.as_ref()));
+    }
+    for (name, value) in common_labels {
+        labels.insert(name.clone(), value.clone());
+    }
+    labels.into_iter().collect()
+}
+
+fn metric_name(name: &str, unit: &str, counter: bool) -> String {
+    let mut name = sanitize(name, |c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    if counter && name.ends_with("_total") {
+        name.truncate(name.len() - "_total".len());
+    }
+    if !unit.is_empty() && !name.ends_with(&format!("_{}", unit)) {
+        name = format!("{}_{}", name, unit);
+    }
+    if counter {
+        name.push_str("_total");
+    }
+    name
+}
+
+fn label_name(key: &str) -> String {
+    let name = sanitize(key, |c| c.is_ascii_alphanumeric() || c == '_');
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("key_{}", name)
+    } else {
+        name
+    }
+}
+
+// replaces the invalid characters with `_`, without repeating it.
+fn sanitize(text: &str, valid: impl Fn(char) -> bool) -> String {
+    let mut sanitized = String::with_capacity(text.len());
+    for c in text.chars() {
+        let c = if valid(c) { c } else { '_' };
+        if c != '_' || !sanitized.ends_with('_') {
+            sanitized.push(c);
+        }
+    }
+    sanitized
+}
+
+/// Converts the UCUM unit to the suffix of the metric name: `By` to `bytes`, `m/s` to `meters_per_second`,
+/// and `1` to `ratio` for gauges. The annotations in braces, like `{request}`, are dropped.
+fn unit_suffix(unit: &str, gauge: bool) -> String {
+    let mut unit_without_annotations = String::new();
+    let mut depth = 0;
+    for c in unit.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            c if depth == 0 => unit_without_annotations.push(c),
+            _ => {},
+        }
+    }
+    let unit = unit_without_annotations.trim();
+    if unit == "1" {
+        return if gauge { "ratio".to_string() } else { String::new() };
+    }
+    let suffix = match unit.split_once('/') {
+        Some((main, per)) if main.is_empty() || main == "1" => format!("per_{}", per_unit(per)),
+        Some((main, per)) => format!("{}_per_{}", main_unit(main), per_unit(per)),
+        None => main_unit(unit),
+    };
+    sanitize(&suffix, |c| c.is_ascii_alphanumeric() || c == '_').trim_matches('_').to_string()
+}
+
+fn main_unit(unit: &str) -> String {
+    match unit {
+        "d" => "days",
+        "h" => "hours",
+        "min" => "minutes",
+        "s" => "seconds",
+        "ms" => "milliseconds",
+        "us" => "microseconds",
+        "ns" => "nanoseconds",
+        "By" => "bytes",
+        "KiBy" => "kibibytes",
+        "MiBy" => "mebibytes",
+        "GiBy" => "gibibytes",
+        "TiBy" => "tibibytes",
+        "KBy" => "kilobytes",
+        "MBy" => "megabytes",
+        "GBy" => "gigabytes",
+        "TBy" => "terabytes",
+        "m" => "meters",
+        "V" => "volts",
+        "A" => "amperes",
+        "J" => "joules",
+        "W" => "watts",
+        "g" => "grams",
+        "Cel" => "celsius",
+        "Hz" => "hertz",
+        "%" => "percent",
+        unit => unit,
+    }.to_string()
+}
+
+fn per_unit(unit: &str) -> String {
+    match unit {
+        "s" => "second",
+        "m" => "minute",
+        "h" => "hour",
+        "d" => "day",
+        "w" => "week",
+        "mo" => "month",
+        "y" => "year",
+        unit => unit,
+    }.to_string()
+}
+
+fn render_family(text: &mut String, name: &str, family: &Family, open_metrics: bool) {
+    // OpenMetrics names the family without the suffixes of its samples.
+    let family_name = match family.family_type {
+        FamilyType::Counter if open_metrics => name.strip_suffix("_total").unwrap_or(name),
+        FamilyType::Info if open_metrics => name.strip_suffix("_info").unwrap_or(name),
+        _ => name,
+    };
+    let type_name = match family.family_type {
+        FamilyType::Counter => "counter",
+        FamilyType::Gauge => "gauge",
+        FamilyType::Histogram => "histogram",
+        FamilyType::Summary => "summary",
+        FamilyType::Info if open_metrics => "info",
+        FamilyType::Info => "gauge",
+    };
+    if !family.help.is_empty() {
+        let _ = writeln!(text, "# HELP {} {}", family_name, escape_help(&family.help));
+    }
+    let _ = writeln!(text, "# TYPE {} {}", family_name, type_name);
+    if open_metrics && !family.unit.is_empty() {
+        let _ = writeln!(text, "# UNIT {} {}", family_name, family.unit);
+    }
+
+    for (labels, value) in &family.series {
+        match value {
+            Value::Number(value) => render_sample(text, name, labels, None, *value),
+            Value::Histogram { bounds, bucket_counts, sum, count } => {
+                // the OTLP buckets count what falls into them, the Prometheus ones everything up to their bound.
+                let mut cumulative = 0;
+                for (bound, bucket_count) in bounds.iter().zip(bucket_counts) {
+                    cumulative += bucket_count;
+                    render_sample(text, &format!("{}_bucket", name), labels, Some(("le", *bound)), cumulative as f64);
+                }
+                render_sample(text, &format!("{}_bucket", name), labels, Some(("le", f64::INFINITY)), *count as f64);
+                render_sample(text, &format!("{}_sum", name), labels, None, *sum);
+                render_sample(text, &format!("{}_count", name), labels, None, *count as f64);
+            },
+            Value::Summary { quantiles, sum, count } => {
+                for (quantile, value) in quantiles {
+                    render_sample(text, name, labels, Some(("quantile", *quantile)), *value);
+                }
+                render_sample(text, &format!("{}_sum", name), labels, None, *sum);
+                render_sample(text, &format!("{}_count", name), labels, None, *count as f64);
+            },
+        }
+    }
+}
+
+fn render_sample(text: &mut String, name: &str, labels: &[(String, String)], extra_label: Option<(&str, f64)>, value: f64) {
+    let mut all_labels = labels.iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect::<Vec<_>>();
+    if let Some((name, value)) = extra_label {
+        all_labels.push(format!("{}=\"{}\"", name, format_value(value)));
+    }
+    if all_labels.is_empty() {
+        let _ = writeln!(text, "{} {}", name, format_value(value));
+    } else {
+        let _ = writeln!(text, "{}{{{}}} {}", name, all_labels.join(","), format_value(value));
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opentelemetry::proto::metrics::v1::{Gauge, Histogram, ResourceMetrics, ScopeMetrics, Sum};
+    use crate::receivers::{service_resource, string_attribute};
+
+    fn request(metrics: Vec<Metric>) -> ExportMetricsServiceRequest {
+        ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(service_resource("checkout", vec![
+                    string_attribute(SERVICE_INSTANCE_ID, "pod-1"),
+                    string_attribute("host.name", "node-a"),
+                ])),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope { name: "shop".to_string(), ..Default::default() }),
+                    metrics,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+
+    fn number(attributes: Vec<KeyValue>, value: f64) -> NumberDataPoint {
+        NumberDataPoint {
+            attributes,
+            value: Some(number_data_point::Value::AsDouble(value)),
+            ..Default::default()
+        }
+    }
+
+    fn counter(name: &str, unit: &str, temporality: AggregationTemporality, value: f64) -> Metric {
+        Metric {
+            name: name.to_string(),
+            description: "Handled requests".to_string(),
+            unit: unit.to_string(),
+            data: Some(metric::Data::Sum(Sum {
+                data_points: vec![number(vec![string_attribute("http.method", "GET")], value)],
+                aggregation_temporality: temporality as i32,
+                is_monotonic: true,
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_text() {
+        let mut exposition = MetricsExposition::new();
+        exposition.update(&request(vec![
+            counter("http.server.requests", "{request}", AggregationTemporality::Cumulative, 3.0),
+            Metric {
+                name: "memory.usage".to_string(),
+                unit: "1".to_string(),
+                data: Some(metric::Data::Gauge(Gauge { data_points: vec![number(vec![], 0.5)] })),
+                ..Default::default()
+            },
+            Metric {
+                name: "http.server.duration".to_string(),
+                unit: "s".to_string(),
+                data: Some(metric::Data::Histogram(Histogram {
+                    data_points: vec![HistogramDataPoint {
+                        count: 4,
+                        sum: Some(1.5),
+                        bucket_counts: vec![1, 2, 1],
+                        explicit_bounds: vec![0.1, 1.0],
+                        ..Default::default()
+                    }],
+                    aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                })),
+                ..Default::default()
+            },
+        ]));
+
+        let expected = r#"# TYPE http_server_duration_seconds histogram
+http_server_duration_seconds_bucket{instance="pod-1",job="checkout",otel_scope_name="shop",le="0.1"} 1
+http_server_duration_seconds_bucket{instance="pod-1",job="checkout",otel_scope_name="shop",le="1"} 3
+http_server_duration_seconds_bucket{instance="pod-1",job="checkout",otel_scope_name="shop",le="+Inf"} 4
+http_server_duration_seconds_sum{instance="pod-1",job="checkout",otel_scope_name="shop"} 1.5
+http_server_duration_seconds_count{instance="pod-1",job="checkout",otel_scope_name="shop"} 4
+# HELP http_server_requests_total Handled requests
+# TYPE http_server_requests_total counter
+http_server_requests_total{http_method="GET",instance="pod-1",job="checkout",otel_scope_name="shop"} 3
+# TYPE memory_usage_ratio gauge
+memory_usage_ratio{instance="pod-1",job="checkout",otel_scope_name="shop"} 0.5
+# HELP target_info Target metadata
+# TYPE target_info gauge
+target_info{host_name="node-a",instance="pod-1",job="checkout"} 1
+"#;
+        assert_eq!(expected, exposition.render(false));
+    }
+
+    #[test]
+    fn test_render_open_metrics() {
+        let mut exposition = MetricsExposition::new();
+        exposition.update(&request(vec![counter("transfer", "By", AggregationTemporality::Delta, 2.0)]));
+        exposition.update(&request(vec![counter("transfer", "By", AggregationTemporality::Delta, 5.0)]));
+
+        let rendered = exposition.render(true);
+        assert!(rendered.contains("# TYPE transfer_bytes counter\n# UNIT transfer_bytes bytes\n"));
+        assert!(rendered.contains("transfer_bytes_total{http_method=\"GET\",instance=\"pod-1\",job=\"checkout\",otel_scope_name=\"shop\"} 7\n"));
+        assert!(rendered.contains("# TYPE target info\n"));
+        assert!(rendered.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_no_recorded_value_removes_series() {
+        let mut exposition = MetricsExposition::new();
+        exposition.update(&request(vec![counter("jobs", "", AggregationTemporality::Cumulative, 1.0)]));
+        let mut stale = counter("jobs", "", AggregationTemporality::Cumulative, 0.0);
+        if let Some(metric::Data::Sum(sum)) = &mut stale.data {
+            sum.data_points[0].flags = DataPointFlags::NoRecordedValueMask as u32;
+        }
+        exposition.update(&request(vec![stale]));
+        assert!(!exposition.render(false).contains("jobs_total"));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!("http_requests_total", metric_name("http.requests", "", true));
+        assert_eq!("http_requests_total", metric_name("http.requests_total", "", true));
+        assert_eq!("_3xx_count", metric_name("3xx..count", "", false));
+        assert_eq!("latency_seconds", metric_name("latency_seconds", "seconds", false));
+        assert_eq!("key_2fa", label_name("2fa"));
+        assert_eq!("meters_per_second", unit_suffix("m/s", false));
+        assert_eq!("per_second", unit_suffix("{packet}/s", false));
+        assert_eq!("", unit_suffix("1", false));
+        assert_eq!("kibibytes", unit_suffix("KiBy", true));
+    }
+}
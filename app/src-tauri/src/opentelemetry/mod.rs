@@ -1 +1,4 @@
-pub(crate) mod proto;
\ No newline at end of file
+pub(crate) mod proto;
+
+/// The encoded descriptors of the OTLP protos, which the grpc server reflection serves.
+pub(crate) const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/opentelemetry_descriptor.bin"));
@@ -0,0 +1,82 @@
+use std::collections::{HashMap, VecDeque};
+use serde::Serialize;
+use crate::subscription_manager::{topic_root, Topic, TopicMessage};
+
+/// Sent after the buffered messages on resume, when some of them did not fit in the buffer.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PauseSummary {
+    pub dropped: HashMap<Topic, u64>,
+}
+
+/// Holds the events for a paused client. When the buffer is full the newer events
+/// are only counted per root topic, so the client can query the store for them.
+pub struct PauseBuffer {
+    limit: usize,
+    messages: VecDeque<TopicMessage>,
+    dropped: HashMap<Topic, u64>,
+}
+
+impl PauseBuffer {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            messages: VecDeque::new(),
+            dropped: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, message: TopicMessage) {
+        if self.messages.len() < self.limit {
+            self.messages.push_back(message);
+        } else {
+            *self.dropped.entry(topic_root(message.topic()).to_string()).or_default() += 1;
+        }
+    }
+
+    /// Returns the buffered messages in the order they arrived and the summary of the dropped ones.
+    pub fn flush(self) -> (VecDeque<TopicMessage>, Option<PauseSummary>) {
+        let summary = if self.dropped.is_empty() {
+            None
+        } else {
+            Some(PauseSummary { dropped: self.dropped })
+        };
+        (self.messages, summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flush_keeps_order() {
+        let mut buffer = PauseBuffer::new(10);
+        buffer.push(TopicMessage::new("logs", "first"));
+        buffer.push(TopicMessage::new("traces", "second"));
+        buffer.push(TopicMessage::new("logs", "third"));
+
+        let (messages, summary) = buffer.flush();
+        let payloads = messages.iter().map(|m| match m {
+            TopicMessage::Any { payload, .. } => payload.as_str(),
+            _ => panic!("Expected topic message"),
+        }).collect::<Vec<_>>();
+        assert_eq!(vec!["first", "second", "third"], payloads);
+        assert_eq!(None, summary);
+    }
+
+    #[test]
+    fn test_overflow_is_counted() {
+        let mut buffer = PauseBuffer::new(2);
+        buffer.push(TopicMessage::new("logs", "1"));
+        buffer.push(TopicMessage::new("logs", "2"));
+        buffer.push(TopicMessage::new("logs", "3"));
+        buffer.push(TopicMessage::new("metrics", "4"));
+        buffer.push(TopicMessage::new("logs/checkout/error", "5"));
+
+        let (messages, summary) = buffer.flush();
+        assert_eq!(2, messages.len());
+        let summary = summary.unwrap();
+        assert_eq!(Some(&2), summary.dropped.get("logs"));
+        assert_eq!(Some(&1), summary.dropped.get("metrics"));
+    }
+}
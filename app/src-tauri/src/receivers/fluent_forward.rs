@@ -0,0 +1,503 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{Cursor, Read};
+use flate2::read::MultiGzDecoder;
+use opentelemetry_semantic_conventions::attribute::LOG_IOSTREAM;
+use opentelemetry_semantic_conventions::resource::{CONTAINER_ID, CONTAINER_NAME};
+use rmpv::Value;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use crate::app_state::AppState;
+use crate::opentelemetry::proto::collector::logs::v1::ExportLogsServiceRequest;
+use crate::opentelemetry::proto::common::v1::{any_value, AnyValue, ArrayValue, KeyValue, KeyValueList};
+use crate::opentelemetry::proto::logs::v1::{LogRecord, ResourceLogs, ScopeLogs};
+use crate::receivers::{attribute, service_resource, string_attribute, to_nanos};
+use crate::server::shutdown_signal;
+
+// fluentd buffers up to 8 MiB per chunk by default, compressed chunks grow when they are decompressed.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+const EVENT_TIME_EXT: i8 = 0;
+const TAG_ATTRIBUTE: &str = "fluent.tag";
+// the record fields which hold the log line, in the order they are looked up.
+const BODY_FIELDS: [&str; 3] = ["log", "message", "msg"];
+const SEVERITY_FIELDS: [&str; 2] = ["level", "severity"];
+// the fields of the docker fluentd logging driver.
+const CONTAINER_NAME_FIELD: &str = "container_name";
+const CONTAINER_ID_FIELD: &str = "container_id";
+const SOURCE_FIELD: &str = "source";
+
+/// A decoded Forward protocol message: the records and the chunk id the sender waits an ack for.
+struct ForwardMessage {
+    request: ExportLogsServiceRequest,
+    chunk: Option<String>,
+}
+
+/// Listens for the Fluentd Forward protocol, which the docker fluentd logging driver, Fluentd and Fluent Bit send.
+/// The records become logs of a service named after the container, or after the tag without container.
+// https://github.com/fluent/fluentd/wiki/Forward-Protocol-Specification-v1
+pub async fn init_fluent_forward(state: AppState, address: String) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(&address).await?;
+    println!("Fluent Forward receiver listening on tcp://{}", address);
+    let accept_loop = async {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tokio::spawn(handle_connection(state.clone(), stream));
+        }
+    };
+    tokio::select! {
+        r = accept_loop => r,
+        _ = shutdown_signal() => Ok(()),
+    }
+}
+
+async fn handle_connection(state: AppState, mut stream: TcpStream) {
+    if let Err(e) = receive_messages(&state, &mut stream).await {
+        println!("Fluent Forward connection closed: {}", e);
+    }
+}
+
+// the messages follow each other on the connection without framing, so they are decoded once they are complete.
+async fn receive_messages(state: &AppState, stream: &mut TcpStream) -> Result<(), Box<dyn Error>> {
+    let mut reader = MessageReader::new();
+    loop {
+        let Some(value) = reader.next(stream).await? else {
+            return Ok(());
+        };
+        // the sender doesn't follow the protocol, the connection is closed rather than guessing what it meant.
+        let message = decode_message(value).map_err(|e| format!("Invalid message: {}", e))?;
+        state.request_processor.process_logs(message.request).await;
+        if let Some(chunk) = message.chunk {
+            let mut ack = Vec::new();
+            rmpv::encode::write_value(&mut ack, &Value::Map(vec![(Value::from("ack"), Value::from(chunk))]))?;
+            stream.write_all(&ack).await?;
+        }
+    }
+}
+
+/// Reads the MessagePack values of a connection, each one is decoded once when it is complete.
+struct MessageReader {
+    buffer: Vec<u8>,
+    scanner: ValueScanner,
+}
+
+impl MessageReader {
+    fn new() -> Self {
+        MessageReader { buffer: Vec::new(), scanner: ValueScanner::new() }
+    }
+
+    /// The next value, `None` at the end of the stream.
+    async fn next(&mut self, stream: &mut (impl AsyncRead + Unpin)) -> Result<Option<Value>, Box<dyn Error>> {
+        loop {
+            if let Some(length) = self.scanner.scan(&self.buffer)? {
+                let value = rmpv::decode::read_value(&mut &self.buffer[..length])
+                    .map_err(|e| format!("Invalid MessagePack: {}", e))?;
+                self.buffer.drain(..length);
+                self.scanner = ValueScanner::new();
+                return Ok(Some(value));
+            }
+            if self.buffer.len() > MAX_MESSAGE_SIZE {
+                return Err("Message too large".into());
+            }
+            self.buffer.reserve(READ_BUFFER_SIZE);
+            if stream.read_buf(&mut self.buffer).await? == 0 {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Finds where the value at the start of the buffer ends from the headers of its items, without decoding them.
+/// It resumes where it stopped, so a large message is scanned once however many reads it takes.
+struct ValueScanner {
+    position: usize,
+    // the items which are not scanned yet, the value itself at first.
+    remaining: u64,
+}
+
+impl ValueScanner {
+    fn new() -> Self {
+        ValueScanner { position: 0, remaining: 1 }
+    }
+
+    /// The length of the value, `None` while the buffer doesn't hold all of it.
+    fn scan(&mut self, buffer: &[u8]) -> Result<Option<usize>, Box<dyn Error>> {
+        while self.remaining > 0 {
+            let Some((size, items)) = item_header(&buffer[self.position..])? else {
+                return Ok(None);
+            };
+            if buffer.len() - self.position < size {
+                return Ok(None);
+            }
+            self.position += size;
+            self.remaining = self.remaining - 1 + items;
+        }
+        Ok(Some(self.position))
+    }
+}
+
+// the size of an item without the items it contains, and how many it contains; `None` while the header is incomplete.
+// https://github.com/msgpack/msgpack/blob/master/spec.md#formats
+fn item_header(bytes: &[u8]) -> Result<Option<(usize, u64)>, Box<dyn Error>> {
+    let Some(&marker) = bytes.first() else {
+        return Ok(None);
+    };
+    // the big endian length after the marker.
+    let length = |width: usize| bytes.get(1..1 + width)
+        .map(|length| length.iter().fold(0, |length, byte| length << 8 | *byte as usize));
+    let header = match marker {
+        0x00..=0x7f | 0xc0 | 0xc2 | 0xc3 | 0xe0..=0xff => Some((1, 0)),
+        0x80..=0x8f => Some((1, 2 * (marker & 0x0f) as u64)),
+        0x90..=0x9f => Some((1, (marker & 0x0f) as u64)),
+        0xa0..=0xbf => Some((1 + (marker & 0x1f) as usize, 0)),
+        0xc1 => return Err("Invalid MessagePack marker".into()),
+        // bin and str
+        0xc4 | 0xd9 => length(1).map(|length| (2 + length, 0)),
+        0xc5 | 0xda => length(2).map(|length| (3 + length, 0)),
+        0xc6 | 0xdb => length(4).map(|length| (5 + length, 0)),
+        // ext, with its type after the length
+        0xc7 => length(1).map(|length| (3 + length, 0)),
+        0xc8 => length(2).map(|length| (4 + length, 0)),
+        0xc9 => length(4).map(|length| (6 + length, 0)),
+        // the numbers and fixext
+        0xcc | 0xd0 => Some((2, 0)),
+        0xcd | 0xd1 => Some((3, 0)),
+        0xce | 0xd2 | 0xca => Some((5, 0)),
+        0xcf | 0xd3 | 0xcb => Some((9, 0)),
+        0xd4 => Some((3, 0)),
+        0xd5 => Some((4, 0)),
+        0xd6 => Some((6, 0)),
+        0xd7 => Some((10, 0)),
+        0xd8 => Some((18, 0)),
+        // array and map
+        0xdc => length(2).map(|length| (3, length as u64)),
+        0xdd => length(4).map(|length| (5, length as u64)),
+        0xde => length(2).map(|length| (3, 2 * length as u64)),
+        0xdf => length(4).map(|length| (5, 2 * length as u64)),
+    };
+    Ok(header)
+}
+
+// Message: [tag, time, record, option]
+// Forward: [tag, [[time, record], ...], option]
+// PackedForward: [tag, the [time, record] entries encoded one after the other, option], gzipped in CompressedPackedForward.
+fn decode_message(value: Value) -> Result<ForwardMessage, Box<dyn Error>> {
+    let Value::Array(items) = value else {
+        return Err("Message is not an array".into());
+    };
+    let tag = items.first().and_then(Value::as_str).ok_or("Message without tag")?;
+    let mut entries = Vec::new();
+    let options = match items.get(1) {
+        Some(Value::Array(forward_entries)) => {
+            for entry in forward_entries {
+                entries.push(decode_entry(entry)?);
+            }
+            items.get(2)
+        },
+        Some(Value::Binary(packed)) => {
+            entries = decode_packed_entries(packed, items.get(2))?;
+            items.get(2)
+        },
+        Some(Value::String(packed)) => {
+            entries = decode_packed_entries(packed.as_bytes(), items.get(2))?;
+            items.get(2)
+        },
+        Some(time) => {
+            entries.push((decode_time(time)?, items.get(2).ok_or("Message without record")?.clone()));
+            items.get(3)
+        },
+        None => return Err("Message without entries".into()),
+    };
+    let chunk = option(options, "chunk").and_then(Value::as_str).map(str::to_string);
+    Ok(ForwardMessage { request: to_otlp(tag, entries), chunk })
+}
+
+fn decode_packed_entries(packed: &[u8], options: Option<&Value>) -> Result<Vec<(u64, Value)>, Box<dyn Error>> {
+    let mut decompressed = Vec::new();
+    let packed = match option(options, "compressed").and_then(Value::as_str) {
+        Some("gzip") => {
+            MultiGzDecoder::new(packed).take(MAX_MESSAGE_SIZE as u64).read_to_end(&mut decompressed)?;
+            &decompressed[..]
+        },
+        Some(compression) => return Err(format!("Unsupported compression: {}", compression).into()),
+        None => packed,
+    };
+    let mut cursor = Cursor::new(packed);
+    let mut entries = Vec::new();
+    while (cursor.position() as usize) < packed.len() {
+        entries.push(decode_entry(&rmpv::decode::read_value(&mut cursor)?)?);
+    }
+    Ok(entries)
+}
+
+fn option<'a>(options: Option<&'a Value>, name: &str) -> Option<&'a Value> {
+    options?.as_map()?.iter()
+        .find(|(key, _)| key.as_str() == Some(name))
+        .map(|(_, value)| value)
+}
+
+fn decode_entry(entry: &Value) -> Result<(u64, Value), Box<dyn Error>> {
+    match entry.as_array().map(Vec::as_slice) {
+        Some([time, record, ..]) => Ok((decode_time(time)?, record.clone())),
+        _ => Err("Entry is not a [time, record] array".into()),
+    }
+}
+
+// the time is either the seconds or the EventTime extension, with the seconds and nanoseconds as big endian u32.
+fn decode_time(time: &Value) -> Result<u64, Box<dyn Error>> {
+    match time {
+        Value::Integer(seconds) => match seconds.as_i64() {
+            Some(seconds) if seconds < 0 => Err("Invalid event time".into()),
+            Some(seconds) => Ok(to_nanos(seconds, 0)),
+            // past the range of the nanoseconds.
+            None => Ok(0),
+        },
+        Value::F64(seconds) => float_time(*seconds),
+        Value::F32(seconds) => float_time(*seconds as f64),
+        Value::Ext(EVENT_TIME_EXT, data) if data.len() == 8 => {
+            let seconds = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            let nanos = i32::try_from(u32::from_be_bytes([data[4], data[5], data[6], data[7]])).map_err(|_| "Invalid event time")?;
+            Ok(to_nanos(seconds as i64, nanos))
+        },
+        _ => Err("Invalid event time".into()),
+    }
+}
+
+fn float_time(seconds: f64) -> Result<u64, Box<dyn Error>> {
+    if seconds.is_sign_negative() || seconds.is_nan() {
+        return Err("Invalid event time".into());
+    }
+    // the conversion saturates, so the times past the range of the nanoseconds become 0.
+    Ok(to_nanos(seconds.trunc() as i64, (seconds.fract() * 1e9) as i32))
+}
+
+fn to_otlp(tag: &str, entries: Vec<(u64, Value)>) -> ExportLogsServiceRequest {
+    // the records of a message come from several containers when they are forwarded by fluentd.
+    let mut resources: BTreeMap<(String, String), Vec<LogRecord>> = BTreeMap::new();
+    for (time_unix_nano, record) in entries {
+        let mut fields = match record {
+            Value::Map(fields) => fields,
+            other => vec![(Value::from(BODY_FIELDS[0]), other)],
+        };
+        let mut take = |name: &str| fields.iter()
+            .position(|(key, _)| key.as_str() == Some(name))
+            .map(|index| fields.remove(index).1);
+
+        let container_name = take(CONTAINER_NAME_FIELD).map(|name| to_string(&name).trim_start_matches('/').to_string()).unwrap_or_default();
+        let container_id = take(CONTAINER_ID_FIELD).map(|id| to_string(&id)).unwrap_or_default();
+        let body = BODY_FIELDS.iter().find_map(|name| take(name));
+        let severity_text = SEVERITY_FIELDS.iter().find_map(|name| take(name)).map(|severity| to_string(&severity)).unwrap_or_default();
+
+        let mut attributes = vec![string_attribute(TAG_ATTRIBUTE, tag)];
+        if let Some(source) = take(SOURCE_FIELD) {
+            attributes.push(string_attribute(LOG_IOSTREAM, to_string(&source)));
+        }
+        attributes.extend(fields.iter().map(|(key, value)| attribute(&to_string(key), to_any_value(value))));
+
+        resources.entry((container_name, container_id)).or_default().push(LogRecord {
+            time_unix_nano,
+            observed_time_unix_nano: chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64,
+            severity_text,
+            body: body.map(|body| AnyValue { value: Some(to_any_value(&body)) }),
+            attributes,
+            ..Default::default()
+        });
+    }
+
+    let resource_logs = resources.into_iter()
+        .map(|((container_name, container_id), log_records)| {
+            let mut resource_attributes = Vec::new();
+            if !container_name.is_empty() {
+                resource_attributes.push(string_attribute(CONTAINER_NAME, &container_name));
+            }
+            if !container_id.is_empty() {
+                resource_attributes.push(string_attribute(CONTAINER_ID, container_id));
+            }
+            let service_name = if container_name.is_empty() { tag } else { &container_name };
+            ResourceLogs {
+                resource: Some(service_resource(service_name, resource_attributes)),
+                scope_logs: vec![ScopeLogs {
+                    log_records,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        })
+        .collect();
+    ExportLogsServiceRequest { resource_logs }
+}
+
+fn to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+        Value::Binary(b) => String::from_utf8_lossy(b).into_owned(),
+        other => other.to_string(),
+    }
+}
+
+fn to_any_value(value: &Value) -> any_value::Value {
+    match value {
+        Value::Nil => any_value::Value::StringValue(String::new()),
+        Value::Boolean(b) => any_value::Value::BoolValue(*b),
+        Value::Integer(i) => match i.as_i64() {
+            Some(i) => any_value::Value::IntValue(i),
+            None => any_value::Value::DoubleValue(i.as_f64().unwrap_or_default()),
+        },
+        Value::F32(f) => any_value::Value::DoubleValue(*f as f64),
+        Value::F64(f) => any_value::Value::DoubleValue(*f),
+        Value::String(_) => any_value::Value::StringValue(to_string(value)),
+        Value::Binary(b) => any_value::Value::BytesValue(b.clone()),
+        Value::Array(values) => any_value::Value::ArrayValue(ArrayValue {
+            values: values.iter().map(|value| AnyValue { value: Some(to_any_value(value)) }).collect(),
+        }),
+        Value::Map(fields) => any_value::Value::KvlistValue(KeyValueList {
+            values: fields.iter()
+                .map(|(key, value)| KeyValue {
+                    key: to_string(key),
+                    value: Some(AnyValue { value: Some(to_any_value(value)) }),
+                    ..Default::default()
+                })
+                .collect(),
+        }),
+        Value::Ext(_, data) => any_value::Value::BytesValue(data.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use super::*;
+    use crate::domain::logs::LogDto;
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, value).unwrap();
+        bytes
+    }
+
+    fn docker_record(line: &str) -> Value {
+        Value::Map(vec![
+            (Value::from("container_id"), Value::from("f1d2d2f924e9")),
+            (Value::from("container_name"), Value::from("/nginx")),
+            (Value::from("source"), Value::from("stderr")),
+            (Value::from("log"), Value::from(line)),
+        ])
+    }
+
+    fn event_time(seconds: u32, nanos: u32) -> Value {
+        let mut data = seconds.to_be_bytes().to_vec();
+        data.extend(nanos.to_be_bytes());
+        Value::Ext(EVENT_TIME_EXT, data)
+    }
+
+    fn logs(request: &ExportLogsServiceRequest) -> Vec<LogDto> {
+        request.resource_logs.iter()
+            .flat_map(|r| r.scope_logs[0].log_records.iter().map(|record| LogDto::from_otlp(record.clone(), None, r.resource.as_ref())))
+            .collect()
+    }
+
+    #[test]
+    fn test_message_mode() {
+        let message = Value::Array(vec![Value::from("docker.f1d2d2f924e9"), event_time(1_700_000_000, 5), docker_record("GET / 200")]);
+        let message = decode_message(message).unwrap();
+        assert_eq!(None, message.chunk);
+
+        let logs = logs(&message.request);
+        assert_eq!(1, logs.len());
+        assert_eq!("GET / 200", logs[0].message);
+        assert_eq!("1700000000000000005", logs[0].time_unix_nano);
+        assert_eq!("nginx", logs[0].resource.service_name);
+        assert_eq!("f1d2d2f924e9", logs[0].resource.attributes[CONTAINER_ID]);
+        assert_eq!("stderr", logs[0].tags[LOG_IOSTREAM]);
+        assert_eq!("docker.f1d2d2f924e9", logs[0].tags[TAG_ATTRIBUTE]);
+    }
+
+    #[test]
+    fn test_forward_mode() {
+        let record = Value::Map(vec![
+            (Value::from("message"), Value::from("started")),
+            (Value::from("level"), Value::from("warn")),
+            (Value::from("pid"), Value::from(42)),
+        ]);
+        let message = Value::Array(vec![
+            Value::from("app.worker"),
+            Value::Array(vec![
+                Value::Array(vec![Value::from(1_700_000_000), record]),
+                Value::Array(vec![Value::from(1_700_000_001), docker_record("ready")]),
+            ]),
+            Value::Map(vec![(Value::from("chunk"), Value::from("p8n9gmxTQVC8/nh2wlKKeQ=="))]),
+        ]);
+        let message = decode_message(message).unwrap();
+        assert_eq!(Some("p8n9gmxTQVC8/nh2wlKKeQ==".to_string()), message.chunk);
+
+        let logs = logs(&message.request);
+        let worker = logs.iter().find(|log| log.message == "started").unwrap();
+        assert_eq!("app.worker", worker.resource.service_name);
+        assert_eq!(crate::domain::logs::Severity::Warn, worker.severity);
+        assert_eq!("42", worker.tags["pid"]);
+        assert!(logs.iter().any(|log| log.message == "ready" && log.resource.service_name == "nginx"));
+    }
+
+    #[test]
+    fn test_compressed_packed_forward_mode() {
+        let mut packed = Vec::new();
+        for line in ["first", "second"] {
+            packed.extend(encode(&Value::Array(vec![event_time(1_700_000_000, 0), docker_record(line)])));
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&packed).unwrap();
+        let message = Value::Array(vec![
+            Value::from("docker.nginx"),
+            Value::Binary(encoder.finish().unwrap()),
+            Value::Map(vec![(Value::from("size"), Value::from(2)), (Value::from("compressed"), Value::from("gzip"))]),
+        ]);
+
+        let logs = logs(&decode_message(message).unwrap().request);
+        assert_eq!(vec!["first", "second"], logs.iter().map(|log| log.message.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_decode_time() {
+        assert_eq!(1_500_000_000, decode_time(&Value::F64(1.5)).unwrap());
+        assert_eq!(0, decode_time(&Value::from(u64::MAX)).unwrap());
+        assert_eq!(0, decode_time(&Value::F64(1e30)).unwrap());
+        assert!(decode_time(&Value::from(-1)).is_err());
+        assert!(decode_time(&Value::F64(-1.0)).is_err());
+    }
+
+    #[test]
+    fn test_scan() {
+        let values = [
+            Value::Array(vec![Value::from("tag"), Value::from(1), Value::Map(vec![])]),
+            Value::Array(vec![Value::from(-300), Value::from(70_000), Value::F64(1.5), Value::Binary(vec![1; 300]), event_time(1, 2)]),
+            Value::Map(vec![(Value::from("a".repeat(40)), Value::Array(vec![Value::Nil; 20]))]),
+        ];
+        for value in values {
+            let bytes = encode(&value);
+            let mut scanner = ValueScanner::new();
+            // fed a byte at a time, the value ends with the last one.
+            for end in 0..bytes.len() {
+                assert_eq!(None, scanner.scan(&bytes[..end]).unwrap());
+            }
+            assert_eq!(Some(bytes.len()), scanner.scan(&bytes).unwrap());
+        }
+        assert!(ValueScanner::new().scan(&[0xc1]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_message_reader() {
+        let message = encode(&Value::Array(vec![Value::from("tag"), Value::from(1), Value::Map(vec![])]));
+        let mut bytes = [message.clone(), message].concat();
+        bytes.push(0xc1);
+        let mut reader = MessageReader::new();
+        let mut stream = &bytes[..];
+        assert!(reader.next(&mut stream).await.unwrap().is_some());
+        assert!(reader.next(&mut stream).await.unwrap().is_some());
+        // the malformed message ends the connection.
+        assert!(reader.next(&mut stream).await.is_err());
+        assert!(MessageReader::new().next(&mut &b""[..]).await.unwrap().is_none());
+        assert!(decode_message(Value::from("not a message")).is_err());
+    }
+}
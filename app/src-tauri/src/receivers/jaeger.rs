@@ -0,0 +1,500 @@
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{middleware, Router};
+use tonic::{Request, Status};
+use crate::app_state::AppState;
+use crate::auth::require_ingest_token;
+use crate::limits::{limit_ingest, read_body};
+use crate::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+use crate::opentelemetry::proto::common::v1::{any_value, KeyValue};
+use crate::opentelemetry::proto::trace::v1::{span, status, ResourceSpans, ScopeSpans, Span, Status as SpanStatus};
+use crate::receivers::{attribute, service_resource, to_nanos};
+use crate::receivers::thrift::{ThriftError, ThriftReader, BOOL, DOUBLE, I32, I64, LIST, STRING, STRUCT};
+use crate::server::shutdown_signal;
+use proto::collector_service_server::{CollectorService, CollectorServiceServer};
+
+mod proto {
+    include!(concat!(env!("OUT_DIR"), "/jaeger.api_v2.rs"));
+}
+
+const THRIFT_CONTENT_TYPE: &str = "application/x-thrift";
+const SPAN_KIND_TAG: &str = "span.kind";
+const ERROR_TAG: &str = "error";
+const STATUS_CODE_TAG: &str = "otel.status_code";
+const STATUS_DESCRIPTION_TAG: &str = "otel.status_description";
+const EVENT_FIELD: &str = "event";
+
+pub async fn init_jaeger(state: AppState, address: String) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = tokio::net::TcpListener::bind(&address).await?;
+    println!("Jaeger receiver listening on http://{}", address);
+    let app = Router::new()
+        .route("/api/traces", post(handle_traces))
+        .route_layer(middleware::from_fn_with_state(state.clone(), limit_ingest))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_ingest_token))
+        .with_state(state);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+    Ok(())
+}
+
+/// The `jaeger.api_v2.CollectorService`, served next to the OTLP services.
+pub fn collector_service(state: AppState) -> CollectorServiceServer<GrpcJaegerCollectorService> {
+    CollectorServiceServer::new(GrpcJaegerCollectorService { state })
+}
+
+pub struct GrpcJaegerCollectorService {
+    state: AppState,
+}
+
+#[tonic::async_trait]
+impl CollectorService for GrpcJaegerCollectorService {
+    async fn post_spans(
+        &self,
+        request: Request<proto::PostSpansRequest>,
+    ) -> Result<tonic::Response<proto::PostSpansResponse>, Status> {
+        let _permit = self.state.ingest_limiter.acquire().await?;
+        if let Some(batch) = request.into_inner().batch {
+            self.state.request_processor.process_traces(to_otlp(batch)).await;
+        }
+        Ok(tonic::Response::new(proto::PostSpansResponse::default()))
+    }
+}
+
+// Resources:
+// Thrift: https://github.com/jaegertracing/jaeger-idl/blob/main/thrift/jaeger.thrift
+// Collector-Go: https://github.com/open-telemetry/opentelemetry-collector-contrib/tree/main/pkg/translator/jaeger
+async fn handle_traces(State(state): State<AppState>, request: axum::extract::Request) -> Response {
+    let is_thrift = request.headers().get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with(THRIFT_CONTENT_TYPE));
+    if !is_thrift {
+        return (StatusCode::BAD_REQUEST, "Not supported content type").into_response();
+    }
+    let body: Bytes = match read_body(request.into_body(), state.config.limits.max_request_size).await {
+        Ok(bytes) => bytes,
+        Err(response) => return response,
+    };
+    match read_batch(&mut ThriftReader::new(&body)) {
+        Ok(batch) => {
+            state.request_processor.process_traces(to_otlp(batch)).await;
+            StatusCode::ACCEPTED.into_response()
+        },
+        Err(e) => (StatusCode::BAD_REQUEST, format!("Failed to decode thrift request body: {}", e)).into_response(),
+    }
+}
+
+// The thrift model is read into the protobuf one, so both transports share the conversion to OTLP.
+
+fn read_batch(reader: &mut ThriftReader) -> Result<proto::Batch, ThriftError> {
+    let mut batch = proto::Batch::default();
+    while let Some((field_type, id)) = reader.read_field_header()? {
+        match (id, field_type) {
+            (1, STRUCT) => batch.process = Some(read_process(reader)?),
+            (2, LIST) => batch.spans = read_list(reader, read_span)?,
+            _ => reader.skip(field_type)?,
+        }
+    }
+    Ok(batch)
+}
+
+fn read_process(reader: &mut ThriftReader) -> Result<proto::Process, ThriftError> {
+    let mut process = proto::Process::default();
+    while let Some((field_type, id)) = reader.read_field_header()? {
+        match (id, field_type) {
+            (1, STRING) => process.service_name = reader.read_string()?,
+            (2, LIST) => process.tags = read_list(reader, read_tag)?,
+            _ => reader.skip(field_type)?,
+        }
+    }
+    Ok(process)
+}
+
+fn read_span(reader: &mut ThriftReader) -> Result<proto::Span, ThriftError> {
+    let mut span = proto::Span::default();
+    let (mut trace_id_low, mut trace_id_high, mut parent_span_id) = (0, 0, 0);
+    while let Some((field_type, id)) = reader.read_field_header()? {
+        match (id, field_type) {
+            (1, I64) => trace_id_low = reader.read_i64()?,
+            (2, I64) => trace_id_high = reader.read_i64()?,
+            (3, I64) => span.span_id = reader.read_i64()?.to_be_bytes().to_vec(),
+            (4, I64) => parent_span_id = reader.read_i64()?,
+            (5, STRING) => span.operation_name = reader.read_string()?,
+            (6, LIST) => span.references = read_list(reader, read_span_ref)?,
+            (7, I32) => span.flags = reader.read_i32()? as u32,
+            (8, I64) => span.start_time = Some(to_timestamp(reader.read_i64()?)),
+            (9, I64) => span.duration = Some(to_duration(reader.read_i64()?)),
+            (10, LIST) => span.tags = read_list(reader, read_tag)?,
+            (11, LIST) => span.logs = read_list(reader, read_log)?,
+            _ => reader.skip(field_type)?,
+        }
+    }
+    span.trace_id = to_trace_id(trace_id_high, trace_id_low);
+    // the parent is a separate field in thrift, in protobuf it is the first child of reference.
+    if parent_span_id != 0 {
+        let parent_id = parent_span_id.to_be_bytes().to_vec();
+        if !span.references.iter().any(|r| r.span_id == parent_id) {
+            span.references.insert(0, proto::SpanRef {
+                trace_id: span.trace_id.clone(),
+                span_id: parent_id,
+                ref_type: proto::SpanRefType::ChildOf.into(),
+            });
+        }
+    }
+    Ok(span)
+}
+
+fn read_span_ref(reader: &mut ThriftReader) -> Result<proto::SpanRef, ThriftError> {
+    let mut span_ref = proto::SpanRef::default();
+    let (mut trace_id_low, mut trace_id_high) = (0, 0);
+    while let Some((field_type, id)) = reader.read_field_header()? {
+        match (id, field_type) {
+            (1, I32) => span_ref.ref_type = reader.read_i32()?,
+            (2, I64) => trace_id_low = reader.read_i64()?,
+            (3, I64) => trace_id_high = reader.read_i64()?,
+            (4, I64) => span_ref.span_id = reader.read_i64()?.to_be_bytes().to_vec(),
+            _ => reader.skip(field_type)?,
+        }
+    }
+    span_ref.trace_id = to_trace_id(trace_id_high, trace_id_low);
+    Ok(span_ref)
+}
+
+fn read_log(reader: &mut ThriftReader) -> Result<proto::Log, ThriftError> {
+    let mut log = proto::Log::default();
+    while let Some((field_type, id)) = reader.read_field_header()? {
+        match (id, field_type) {
+            (1, I64) => log.timestamp = Some(to_timestamp(reader.read_i64()?)),
+            (2, LIST) => log.fields = read_list(reader, read_tag)?,
+            _ => reader.skip(field_type)?,
+        }
+    }
+    Ok(log)
+}
+
+fn read_tag(reader: &mut ThriftReader) -> Result<proto::KeyValue, ThriftError> {
+    let mut tag = proto::KeyValue::default();
+    while let Some((field_type, id)) = reader.read_field_header()? {
+        match (id, field_type) {
+            (1, STRING) => tag.key = reader.read_string()?,
+            // the thrift tag types are ordered differently than the protobuf ones.
+            (2, I32) => tag.v_type = match reader.read_i32()? {
+                1 => proto::ValueType::Float64,
+                2 => proto::ValueType::Bool,
+                3 => proto::ValueType::Int64,
+                4 => proto::ValueType::Binary,
+                _ => proto::ValueType::String,
+            }.into(),
+            (3, STRING) => tag.v_str = reader.read_string()?,
+            (4, DOUBLE) => tag.v_float64 = reader.read_double()?,
+            (5, BOOL) => tag.v_bool = reader.read_bool()?,
+            (6, I64) => tag.v_int64 = reader.read_i64()?,
+            (7, STRING) => tag.v_binary = reader.read_binary()?,
+            _ => reader.skip(field_type)?,
+        }
+    }
+    Ok(tag)
+}
+
+fn read_list<T>(reader: &mut ThriftReader, read: fn(&mut ThriftReader) -> Result<T, ThriftError>) -> Result<Vec<T>, ThriftError> {
+    let (element_type, size) = reader.read_list_header()?;
+    let mut values = Vec::new();
+    for _ in 0..size {
+        if element_type == STRUCT {
+            values.push(read(reader)?);
+        } else {
+            reader.skip(element_type)?;
+        }
+    }
+    Ok(values)
+}
+
+fn to_trace_id(high: i64, low: i64) -> Vec<u8> {
+    [high.to_be_bytes(), low.to_be_bytes()].concat()
+}
+
+fn to_timestamp(micros: i64) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: micros.div_euclid(1_000_000),
+        nanos: (micros.rem_euclid(1_000_000) * 1000) as i32,
+    }
+}
+
+fn to_duration(micros: i64) -> prost_types::Duration {
+    prost_types::Duration {
+        seconds: micros.div_euclid(1_000_000),
+        nanos: (micros.rem_euclid(1_000_000) * 1000) as i32,
+    }
+}
+
+/// Every process becomes a resource, the spans which carry their own process get a resource of their own.
+fn to_otlp(batch: proto::Batch) -> ExportTraceServiceRequest {
+    let mut resource_spans = Vec::new();
+    let mut batch_spans = Vec::new();
+    for mut jaeger_span in batch.spans {
+        match jaeger_span.process.take() {
+            Some(process) => resource_spans.push(to_resource_spans(process, vec![to_otlp_span(jaeger_span)])),
+            None => batch_spans.push(to_otlp_span(jaeger_span)),
+        }
+    }
+    if !batch_spans.is_empty() {
+        resource_spans.insert(0, to_resource_spans(batch.process.unwrap_or_default(), batch_spans));
+    }
+    ExportTraceServiceRequest { resource_spans }
+}
+
+fn to_resource_spans(process: proto::Process, spans: Vec<Span>) -> ResourceSpans {
+    let attributes = process.tags.into_iter().map(to_attribute).collect();
+    ResourceSpans {
+        resource: Some(service_resource(&process.service_name, attributes)),
+        scope_spans: vec![ScopeSpans { spans, ..Default::default() }],
+        ..Default::default()
+    }
+}
+
+fn to_otlp_span(jaeger_span: proto::Span) -> Span {
+    let start = jaeger_span.start_time.as_ref().map(timestamp_nanos).unwrap_or_default();
+    let duration = jaeger_span.duration.as_ref().map(|d| to_nanos(d.seconds, d.nanos)).unwrap_or_default();
+
+    // the parent is the first child of reference within the same trace, the other references are links.
+    let mut references = jaeger_span.references;
+    let parent = references.iter()
+        .position(|r| r.ref_type() == proto::SpanRefType::ChildOf && r.trace_id == jaeger_span.trace_id)
+        .map(|index| references.remove(index));
+    let links = references.into_iter().map(|r| span::Link {
+        trace_id: r.trace_id,
+        span_id: r.span_id,
+        ..Default::default()
+    }).collect();
+
+    let mut kind = span::SpanKind::Unspecified;
+    let mut status_code = status::StatusCode::Unset;
+    let mut status_message = String::new();
+    let mut attributes = Vec::new();
+    for tag in jaeger_span.tags {
+        match tag.key.as_str() {
+            SPAN_KIND_TAG => kind = match tag.v_str.as_str() {
+                "client" => span::SpanKind::Client,
+                "server" => span::SpanKind::Server,
+                "producer" => span::SpanKind::Producer,
+                "consumer" => span::SpanKind::Consumer,
+                "internal" => span::SpanKind::Internal,
+                _ => span::SpanKind::Unspecified,
+            },
+            STATUS_CODE_TAG => status_code = match tag.v_str.to_uppercase().as_str() {
+                "OK" => status::StatusCode::Ok,
+                "ERROR" => status::StatusCode::Error,
+                _ => status_code,
+            },
+            STATUS_DESCRIPTION_TAG => status_message = tag.v_str,
+            ERROR_TAG if tag.v_bool || tag.v_str == "true" => {
+                if status_code == status::StatusCode::Unset {
+                    status_code = status::StatusCode::Error;
+                }
+            },
+            _ => attributes.push(to_attribute(tag)),
+        }
+    }
+
+    let events = jaeger_span.logs.into_iter().map(|log| {
+        let mut name = String::new();
+        let mut attributes = Vec::new();
+        for field in log.fields {
+            if field.key == EVENT_FIELD && field.v_type() == proto::ValueType::String {
+                name = field.v_str;
+            } else {
+                attributes.push(to_attribute(field));
+            }
+        }
+        span::Event {
+            time_unix_nano: log.timestamp.as_ref().map(timestamp_nanos).unwrap_or_default(),
+            name,
+            attributes,
+            ..Default::default()
+        }
+    }).collect();
+
+    Span {
+        trace_id: jaeger_span.trace_id,
+        span_id: jaeger_span.span_id,
+        parent_span_id: parent.map(|r| r.span_id).unwrap_or_default(),
+        name: jaeger_span.operation_name,
+        kind: kind.into(),
+        start_time_unix_nano: start,
+        end_time_unix_nano: start.saturating_add(duration),
+        attributes,
+        events,
+        links,
+        status: Some(SpanStatus {
+            message: status_message,
+            code: status_code.into(),
+        }),
+        ..Default::default()
+    }
+}
+
+fn to_attribute(tag: proto::KeyValue) -> KeyValue {
+    let value = match tag.v_type() {
+        proto::ValueType::String => any_value::Value::StringValue(tag.v_str),
+        proto::ValueType::Bool => any_value::Value::BoolValue(tag.v_bool),
+        proto::ValueType::Int64 => any_value::Value::IntValue(tag.v_int64),
+        proto::ValueType::Float64 => any_value::Value::DoubleValue(tag.v_float64),
+        proto::ValueType::Binary => any_value::Value::BytesValue(tag.v_binary),
+    };
+    attribute(&tag.key, value)
+}
+
+fn timestamp_nanos(timestamp: &prost_types::Timestamp) -> u64 {
+    to_nanos(timestamp.seconds, timestamp.nanos)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::traces::{SpanDto, SpanKind, SpanStatusCode};
+
+    // a minimal thrift binary writer for the test batches.
+    fn field(bytes: &mut Vec<u8>, field_type: u8, id: i16) {
+        bytes.push(field_type);
+        bytes.extend(id.to_be_bytes());
+    }
+
+    fn string_field(bytes: &mut Vec<u8>, id: i16, value: &str) {
+        field(bytes, STRING, id);
+        bytes.extend((value.len() as i32).to_be_bytes());
+        bytes.extend(value.as_bytes());
+    }
+
+    fn i64_field(bytes: &mut Vec<u8>, id: i16, value: i64) {
+        field(bytes, I64, id);
+        bytes.extend(value.to_be_bytes());
+    }
+
+    fn list_field(bytes: &mut Vec<u8>, id: i16, elements: Vec<Vec<u8>>) {
+        field(bytes, LIST, id);
+        bytes.push(STRUCT);
+        bytes.extend((elements.len() as i32).to_be_bytes());
+        elements.into_iter().for_each(|element| bytes.extend(element));
+    }
+
+    fn string_tag(key: &str, value: &str) -> Vec<u8> {
+        let mut tag = Vec::new();
+        string_field(&mut tag, 1, key);
+        field(&mut tag, I32, 2);
+        tag.extend(0i32.to_be_bytes());
+        string_field(&mut tag, 3, value);
+        tag.push(0);
+        tag
+    }
+
+    fn thrift_batch() -> Vec<u8> {
+        let mut process = Vec::new();
+        string_field(&mut process, 1, "legacy");
+        list_field(&mut process, 2, vec![string_tag("hostname", "box")]);
+        process.push(0);
+
+        let mut span = Vec::new();
+        i64_field(&mut span, 1, 2);
+        i64_field(&mut span, 2, 1);
+        i64_field(&mut span, 3, 3);
+        i64_field(&mut span, 4, 4);
+        string_field(&mut span, 5, "GET /orders");
+        i64_field(&mut span, 8, 1_000_000);
+        i64_field(&mut span, 9, 1500);
+        list_field(&mut span, 10, vec![string_tag("span.kind", "server"), string_tag("error", "true"), string_tag("http.method", "GET")]);
+        let mut log = Vec::new();
+        i64_field(&mut log, 1, 1_000_100);
+        list_field(&mut log, 2, vec![string_tag("event", "retry"), string_tag("attempt", "2")]);
+        log.push(0);
+        list_field(&mut span, 11, vec![log]);
+        // an unknown field, which is skipped.
+        string_field(&mut span, 99, "ignored");
+        span.push(0);
+
+        let mut batch = Vec::new();
+        field(&mut batch, STRUCT, 1);
+        batch.extend(process);
+        list_field(&mut batch, 2, vec![span]);
+        batch.push(0);
+        batch
+    }
+
+    fn to_dtos(request: ExportTraceServiceRequest) -> Vec<SpanDto> {
+        request.resource_spans.into_iter().flat_map(|resource_spans| {
+            let resource = resource_spans.resource;
+            resource_spans.scope_spans.into_iter()
+                .flat_map(|scope_spans| scope_spans.spans)
+                .map(|span| SpanDto::from_otlp(span, None, resource.as_ref()))
+                .collect::<Vec<_>>()
+        }).collect()
+    }
+
+    #[test]
+    fn test_thrift_batch() {
+        let batch = read_batch(&mut ThriftReader::new(&thrift_batch())).unwrap();
+        let dtos = to_dtos(to_otlp(batch));
+        assert_eq!(1, dtos.len());
+
+        let span = &dtos[0];
+        assert_eq!("legacy", span.resource.service_name);
+        assert_eq!(Some(&"box".to_string()), span.resource.attributes.get("hostname"));
+        assert_eq!("\"00000000000000010000000000000002\"", serde_json::to_string(&span.trace_id).unwrap());
+        assert_eq!("\"0000000000000004\"", serde_json::to_string(&span.parent_span_id).unwrap());
+        assert_eq!("GET /orders", span.name);
+        assert!(matches!(span.kind, SpanKind::Server));
+        assert!(matches!(span.status.code, SpanStatusCode::Error));
+        assert_eq!(1_000_000_000, span.start_time.timestamp_nanos_opt().unwrap());
+        assert_eq!(1_001_500_000, span.end_time.timestamp_nanos_opt().unwrap());
+        assert_eq!(1, span.tags.len());
+        assert_eq!(Some(&"GET".to_string()), span.tags.get("http.method"));
+        assert_eq!("retry", span.events[0].name);
+        assert_eq!(Some(&"2".to_string()), span.events[0].attributes.get("attempt"));
+        assert!(span.links.is_empty());
+    }
+
+    #[test]
+    fn test_proto_batch() {
+        let trace_id = vec![1; 16];
+        let batch = proto::Batch {
+            process: Some(proto::Process { service_name: "batch".to_string(), tags: Vec::new() }),
+            spans: vec![
+                proto::Span {
+                    trace_id: trace_id.clone(),
+                    span_id: vec![2; 8],
+                    operation_name: "first".to_string(),
+                    references: vec![
+                        proto::SpanRef { trace_id: vec![3; 16], span_id: vec![4; 8], ref_type: proto::SpanRefType::FollowsFrom.into() },
+                        proto::SpanRef { trace_id: trace_id.clone(), span_id: vec![5; 8], ref_type: proto::SpanRefType::ChildOf.into() },
+                    ],
+                    tags: vec![proto::KeyValue { key: "retries".to_string(), v_type: proto::ValueType::Int64.into(), v_int64: 3, ..Default::default() }],
+                    ..Default::default()
+                },
+                proto::Span {
+                    trace_id,
+                    span_id: vec![6; 8],
+                    operation_name: "second".to_string(),
+                    process: Some(proto::Process { service_name: "own".to_string(), tags: Vec::new() }),
+                    ..Default::default()
+                },
+            ],
+        };
+        let dtos = to_dtos(to_otlp(batch));
+        assert_eq!(2, dtos.len());
+        assert_eq!(("batch", "first"), (dtos[0].resource.service_name.as_str(), dtos[0].name.as_str()));
+        assert_eq!("\"0505050505050505\"", serde_json::to_string(&dtos[0].parent_span_id).unwrap());
+        assert_eq!(1, dtos[0].links.len());
+        assert_eq!(Some(&"3".to_string()), dtos[0].tags.get("retries"));
+        assert_eq!(("own", "second"), (dtos[1].resource.service_name.as_str(), dtos[1].name.as_str()));
+        assert!(dtos[1].parent_span_id.is_none());
+    }
+
+    #[test]
+    fn test_to_duration() {
+        let duration = to_duration(-1_500_000);
+        assert_eq!((-2, 500_000_000), (duration.seconds, duration.nanos));
+    }
+}
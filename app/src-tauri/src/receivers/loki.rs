@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use prost::Message as ProstMessage;
+use serde::Deserialize;
+use crate::app_state::AppState;
+use crate::limits::{exceeds_snappy_size, payload_too_large, read_body};
+use crate::opentelemetry::proto::collector::logs::v1::ExportLogsServiceRequest;
+use crate::opentelemetry::proto::common::v1::{any_value, AnyValue};
+use crate::opentelemetry::proto::logs::v1::{LogRecord, ResourceLogs, ScopeLogs};
+use crate::receivers::prometheus_text::{parse_labels, Labels};
+use crate::receivers::{service_resource, string_attribute, to_nanos};
+
+mod proto {
+    include!(concat!(env!("OUT_DIR"), "/logproto.rs"));
+}
+
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+const JSON_CONTENT_TYPE: &str = "application/json";
+// the labels which name the service, in the order they are looked up.
+const SERVICE_LABELS: [&str; 6] = ["service_name", "service", "app", "application", "job", "container"];
+// the labels and structured metadata which hold the level of the line.
+const LEVEL_LABELS: [&str; 3] = ["level", "detected_level", "severity"];
+// what Loki names a stream without service.
+const UNKNOWN_SERVICE: &str = "unknown_service";
+
+#[derive(Deserialize)]
+struct JsonPushRequest {
+    streams: Vec<JsonStream>,
+}
+
+#[derive(Deserialize)]
+struct JsonStream {
+    stream: BTreeMap<String, String>,
+    values: Vec<JsonEntry>,
+}
+
+// [<unix epoch in nanoseconds>, <line>, <structured metadata>], where the structured metadata is optional.
+#[derive(Deserialize)]
+struct JsonEntry(String, String, #[serde(default)] BTreeMap<String, String>);
+
+/// A stream of the push request with its labels parsed, the same for both formats.
+struct Stream {
+    labels: Labels,
+    entries: Vec<proto::EntryAdapter>,
+}
+
+impl TryFrom<JsonStream> for Stream {
+    type Error = String;
+
+    fn try_from(stream: JsonStream) -> Result<Self, Self::Error> {
+        let entries = stream.values.into_iter()
+            .map(|JsonEntry(time, line, metadata)| {
+                let nanos = time.parse::<i64>().map_err(|_| format!("Invalid timestamp: {}", time))?;
+                Ok(proto::EntryAdapter {
+                    timestamp: Some(prost_types::Timestamp {
+                        seconds: nanos.div_euclid(1_000_000_000),
+                        nanos: nanos.rem_euclid(1_000_000_000) as i32,
+                    }),
+                    line,
+                    structured_metadata: metadata.into_iter()
+                        .map(|(name, value)| proto::LabelPairAdapter { name, value })
+                        .collect(),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Stream { labels: stream.stream.into_iter().collect(), entries })
+    }
+}
+
+impl TryFrom<proto::StreamAdapter> for Stream {
+    type Error = String;
+
+    fn try_from(stream: proto::StreamAdapter) -> Result<Self, Self::Error> {
+        let labels = stream.labels.trim().strip_prefix('{')
+            .ok_or_else(|| format!("Invalid labels: {}", stream.labels))
+            .and_then(|labels| parse_labels(labels).map_err(|e| format!("Invalid labels {}: {}", stream.labels, e)))?
+            .0;
+        Ok(Stream { labels, entries: stream.entries })
+    }
+}
+
+// Resources:
+// Api: https://grafana.com/docs/loki/latest/reference/loki-http-api/#ingest-logs
+// Collector-Go: https://github.com/open-telemetry/opentelemetry-collector-contrib/tree/main/receiver/lokireceiver
+pub async fn handle_push(State(state): State<AppState>, request: axum::extract::Request) -> Response {
+    let content_type = request.headers().get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let max_size = state.config.limits.max_request_size;
+    let body = match read_body(request.into_body(), max_size).await {
+        Ok(bytes) => bytes,
+        Err(response) => return response,
+    };
+    let streams = if content_type.starts_with(JSON_CONTENT_TYPE) {
+        serde_json::from_slice::<JsonPushRequest>(&body)
+            .map_err(|e| format!("Failed to decode json request body: {}", e))
+            .and_then(|push| push.streams.into_iter().map(Stream::try_from).collect::<Result<Vec<_>, _>>())
+    } else if content_type.starts_with(PROTOBUF_CONTENT_TYPE) {
+        if exceeds_snappy_size(&body, max_size) {
+            return payload_too_large(max_size);
+        }
+        // like remote write, the protobuf body uses the snappy block format.
+        snap::raw::Decoder::new().decompress_vec(&body)
+            .map_err(|e| format!("Failed to decompress snappy request body: {}", e))
+            .and_then(|body| proto::PushRequest::decode(body.as_slice())
+                .map_err(|e| format!("Failed to decode protobuf request body: {}", e)))
+            .and_then(|push| push.streams.into_iter().map(Stream::try_from).collect::<Result<Vec<_>, _>>())
+    } else {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Not supported content type").into_response();
+    };
+    match streams {
+        Ok(streams) => {
+            state.request_processor.process_logs(to_otlp(streams)).await;
+            StatusCode::NO_CONTENT.into_response()
+        },
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+// every stream becomes a resource: the service label names it, the other labels are its attributes.
+fn to_otlp(streams: Vec<Stream>) -> ExportLogsServiceRequest {
+    let resource_logs = streams.into_iter()
+        .map(|stream| {
+            let mut labels = stream.labels;
+            let take = |labels: &mut Labels, names: &[&str]| names.iter()
+                .find_map(|name| labels.iter().position(|(label, _)| label == name))
+                .map(|index| labels.remove(index).1);
+            let service_name = take(&mut labels, &SERVICE_LABELS).unwrap_or_else(|| UNKNOWN_SERVICE.to_string());
+            let stream_level = take(&mut labels, &LEVEL_LABELS);
+
+            let log_records = stream.entries.into_iter()
+                .map(|entry| {
+                    let mut metadata = entry.structured_metadata.into_iter()
+                        .map(|pair| (pair.name, pair.value))
+                        .collect::<Labels>();
+                    let level = take(&mut metadata, &LEVEL_LABELS).or_else(|| stream_level.clone());
+                    let time_unix_nano = entry.timestamp.map(|t| to_nanos(t.seconds, t.nanos)).unwrap_or_default();
+                    LogRecord {
+                        time_unix_nano,
+                        observed_time_unix_nano: chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64,
+                        severity_text: level.unwrap_or_default(),
+                        body: Some(AnyValue { value: Some(any_value::Value::StringValue(entry.line)) }),
+                        attributes: metadata.iter().map(|(name, value)| string_attribute(name, value)).collect(),
+                        ..Default::default()
+                    }
+                })
+                .collect();
+
+            let attributes = labels.iter().map(|(name, value)| string_attribute(name, value)).collect();
+            ResourceLogs {
+                resource: Some(service_resource(&service_name, attributes)),
+                scope_logs: vec![ScopeLogs {
+                    log_records,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        })
+        .collect();
+    ExportLogsServiceRequest { resource_logs }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::logs::{LogDto, Severity};
+
+    fn logs(request: ExportLogsServiceRequest) -> Vec<LogDto> {
+        request.resource_logs.iter()
+            .flat_map(|r| r.scope_logs[0].log_records.iter().map(|record| LogDto::from_otlp(record.clone(), None, r.resource.as_ref())))
+            .collect()
+    }
+
+    #[test]
+    fn test_json_push() {
+        let json = r#"{"streams": [
+            {"stream": {"job": "varlogs", "app": "checkout", "level": "warn"},
+             "values": [["1700000000000000005", "disk almost full"],
+                        ["1700000001000000000", "retrying", {"trace_id": "abc", "level": "error"}]]}
+        ]}"#;
+        let push = serde_json::from_str::<JsonPushRequest>(json).unwrap();
+        let streams = push.streams.into_iter().map(Stream::try_from).collect::<Result<Vec<_>, _>>().unwrap();
+        let logs = logs(to_otlp(streams));
+
+        assert_eq!(2, logs.len());
+        assert_eq!("checkout", logs[0].resource.service_name);
+        assert_eq!("varlogs", logs[0].resource.attributes["job"]);
+        assert_eq!("disk almost full", logs[0].message);
+        assert_eq!("1700000000000000005", logs[0].time_unix_nano);
+        assert_eq!(Severity::Warn, logs[0].severity);
+        assert_eq!(Severity::Error, logs[1].severity);
+        assert_eq!("abc", logs[1].tags["trace_id"]);
+    }
+
+    #[test]
+    fn test_protobuf_push() {
+        let push = proto::PushRequest {
+            streams: vec![proto::StreamAdapter {
+                labels: r#"{service_name="billing", env="dev"}"#.to_string(),
+                entries: vec![proto::EntryAdapter {
+                    timestamp: Some(prost_types::Timestamp { seconds: 1_700_000_000, nanos: 7 }),
+                    line: "invoice sent".to_string(),
+                    structured_metadata: vec![],
+                }],
+                hash: 0,
+            }],
+        };
+        let streams = push.streams.into_iter().map(Stream::try_from).collect::<Result<Vec<_>, _>>().unwrap();
+        let logs = logs(to_otlp(streams));
+
+        assert_eq!("billing", logs[0].resource.service_name);
+        assert_eq!("dev", logs[0].resource.attributes["env"]);
+        assert_eq!("invoice sent", logs[0].message);
+        assert_eq!("1700000000000000007", logs[0].time_unix_nano);
+    }
+
+    #[test]
+    fn test_out_of_range_timestamps() {
+        let entry = |seconds| proto::EntryAdapter {
+            timestamp: Some(prost_types::Timestamp { seconds, nanos: 0 }),
+            line: "line".to_string(),
+            structured_metadata: vec![],
+        };
+        let stream = proto::StreamAdapter {
+            labels: r#"{service_name="billing"}"#.to_string(),
+            entries: vec![entry(i64::MAX), entry(-5)],
+            hash: 0,
+        };
+        let request = to_otlp(vec![Stream::try_from(stream).unwrap()]);
+        let records = &request.resource_logs[0].scope_logs[0].log_records;
+        assert_eq!(vec![0, 0], records.iter().map(|record| record.time_unix_nano).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_invalid_labels() {
+        let stream = proto::StreamAdapter { labels: "job=\"x\"".to_string(), ..Default::default() };
+        assert!(Stream::try_from(stream).is_err());
+    }
+}
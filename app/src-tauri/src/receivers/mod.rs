@@ -0,0 +1,105 @@
+use std::error::Error;
+use std::future::Future;
+use futures::future::join_all;
+use futures::FutureExt;
+use crate::app_state::AppState;
+use crate::opentelemetry::proto::common::v1::{any_value, AnyValue, KeyValue};
+use crate::opentelemetry::proto::resource::v1::Resource;
+
+pub(crate) mod zipkin;
+pub(crate) mod jaeger;
+pub(crate) mod prometheus;
+pub(crate) mod loki;
+mod fluent_forward;
+mod prometheus_text;
+mod scrape;
+mod statsd;
+mod syslog;
+mod thrift;
+
+/// Starts the receivers for the non OTLP formats. They translate what they receive into OTLP export requests,
+/// so it goes through the `RequestProcessor` like everything else.
+/// A receiver which fails only logs the error, the OTLP servers keep running.
+pub async fn init_receivers(state: AppState) {
+    let mut receivers = Vec::new();
+    if let Some(address) = state.config.zipkin_address.clone() {
+        receivers.push(run("Zipkin", zipkin::init_zipkin(state.clone(), address)).boxed());
+    }
+    if let Some(address) = state.config.jaeger_address.clone() {
+        receivers.push(run("Jaeger", jaeger::init_jaeger(state.clone(), address)).boxed());
+    }
+    if let Some(address) = state.config.syslog_address.clone() {
+        receivers.push(run("Syslog", syslog::init_syslog(state.clone(), address)).boxed());
+    }
+    if let Some(address) = state.config.fluent_forward_address.clone() {
+        receivers.push(run("Fluent Forward", fluent_forward::init_fluent_forward(state.clone(), address)).boxed());
+    }
+    if let Some(statsd) = state.config.statsd.clone() {
+        receivers.push(run("StatsD", statsd::init_statsd(state.clone(), statsd)).boxed());
+    }
+    if let Some(scrape) = state.config.scrape.clone() {
+        receivers.push(run("Scrape", scrape::init_scrape(state.clone(), scrape)).boxed());
+    }
+    join_all(receivers).await;
+}
+
+async fn run(name: &'static str, receiver: impl Future<Output = Result<(), Box<dyn Error>>>) {
+    if let Err(e) = receiver.await {
+        println!("{} receiver stopped: {}", name, e);
+    }
+}
+
+pub(crate) fn attribute(key: &str, value: any_value::Value) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue { value: Some(value) }),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn string_attribute(key: &str, value: impl Into<String>) -> KeyValue {
+    attribute(key, any_value::Value::StringValue(value.into()))
+}
+
+pub(crate) fn int_attribute(key: &str, value: i64) -> KeyValue {
+    attribute(key, any_value::Value::IntValue(value))
+}
+
+pub(crate) fn service_resource(service_name: &str, mut attributes: Vec<KeyValue>) -> Resource {
+    attributes.insert(0, string_attribute(opentelemetry_semantic_conventions::resource::SERVICE_NAME, service_name));
+    Resource {
+        attributes,
+        ..Default::default()
+    }
+}
+
+/// The nanoseconds since the epoch of a sender's seconds and nanoseconds, 0 before 1970 or past the range of the nanoseconds.
+pub(crate) fn to_nanos(seconds: i64, nanos: i32) -> u64 {
+    seconds.checked_mul(1_000_000_000)
+        .and_then(|n| n.checked_add(nanos as i64))
+        .and_then(|n| u64::try_from(n).ok())
+        .unwrap_or_default()
+}
+
+/// The nanoseconds since the epoch of a time in a unit smaller than the second, by the same rule as `to_nanos`.
+pub(crate) fn unit_to_nanos(value: i64, nanos_per_unit: i64) -> u64 {
+    value.checked_mul(nanos_per_unit)
+        .and_then(|n| u64::try_from(n).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_nanos() {
+        assert_eq!(1_500_000_000, to_nanos(1, 500_000_000));
+        assert_eq!(0, to_nanos(i64::MAX, 0));
+        assert_eq!(0, to_nanos(-1, 0));
+
+        assert_eq!(1_500_000, unit_to_nanos(1_500, 1000));
+        assert_eq!(0, unit_to_nanos(i64::MAX, 1000));
+        assert_eq!(0, unit_to_nanos(-1, 1000));
+    }
+}
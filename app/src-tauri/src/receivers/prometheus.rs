@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, HashMap};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use prost::Message as ProstMessage;
+use opentelemetry_semantic_conventions::resource::SERVICE_INSTANCE_ID;
+use crate::app_state::AppState;
+use crate::limits::{exceeds_snappy_size, payload_too_large, read_body};
+use crate::opentelemetry::proto::collector::metrics::v1::ExportMetricsServiceRequest;
+use crate::opentelemetry::proto::common::v1::KeyValue;
+use crate::opentelemetry::proto::metrics::v1::{metric, number_data_point, AggregationTemporality, Gauge, Histogram, HistogramDataPoint, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum};
+use crate::receivers::{service_resource, string_attribute, unit_to_nanos};
+use proto::metric_metadata::MetricType;
+
+mod proto {
+    include!(concat!(env!("OUT_DIR"), "/prometheus.rs"));
+}
+
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+// remote write 2.0 announces its message in the content type, 1.0 may leave it out.
+const WRITE_REQUEST_PROTO: &str = "proto=prometheus.WriteRequest";
+const NAME_LABEL: &str = "__name__";
+pub(crate) const JOB_LABEL: &str = "job";
+pub(crate) const INSTANCE_LABEL: &str = "instance";
+// the series which prometheus keeps for the counters, histograms and summaries.
+pub(crate) const COUNTER_SUFFIXES: [&str; 4] = ["_total", "_count", "_sum", "_bucket"];
+
+// Resources:
+// Spec: https://prometheus.io/docs/specs/prw/remote_write_spec/
+// Collector-Go: https://github.com/open-telemetry/opentelemetry-collector-contrib/tree/main/receiver/prometheusremotewritereceiver
+pub async fn handle_remote_write(State(state): State<AppState>, request: axum::extract::Request) -> Response {
+    let content_type = request.headers().get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let proto = content_type.split(';').skip(1).map(str::trim).find(|p| p.starts_with("proto="));
+    if !content_type.starts_with(PROTOBUF_CONTENT_TYPE) || proto.is_some_and(|p| p != WRITE_REQUEST_PROTO) {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Only remote write 1.0 protobuf requests are supported").into_response();
+    }
+    let max_size = state.config.limits.max_request_size;
+    let body = match read_body(request.into_body(), max_size).await {
+        Ok(bytes) => bytes,
+        Err(response) => return response,
+    };
+    if exceeds_snappy_size(&body, max_size) {
+        return payload_too_large(max_size);
+    }
+    // remote write uses the snappy block format, not the framed one.
+    let body = match snap::raw::Decoder::new().decompress_vec(&body) {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Failed to decompress snappy request body: {}", e)).into_response(),
+    };
+    match proto::WriteRequest::decode(body.as_slice()) {
+        Ok(write_request) => {
+            state.request_processor.process_metrics(to_otlp(write_request)).await;
+            StatusCode::NO_CONTENT.into_response()
+        },
+        Err(e) => (StatusCode::BAD_REQUEST, format!("Failed to decode protobuf request body: {}", e)).into_response(),
+    }
+}
+
+fn to_otlp(write_request: proto::WriteRequest) -> ExportMetricsServiceRequest {
+    let metadata = write_request.metadata.iter()
+        .map(|m| (m.metric_family_name.as_str(), m))
+        .collect::<HashMap<_, _>>();
+    let mut builder = MetricsBuilder::default();
+    for series in write_request.timeseries {
+        let labels = series.labels.into_iter().map(|l| (l.name, l.value)).collect::<Vec<_>>();
+        let Some(name) = labels.iter().find(|(name, _)| name == NAME_LABEL).map(|(_, value)| value.clone()) else {
+            continue;
+        };
+        let metadata = metadata.get(name.as_str())
+            .or_else(|| metadata.get(family_name(&name)))
+            .copied();
+        let family = MetricFamily {
+            kind: metric_kind(&name, metadata.map(proto::MetricMetadata::r#type)),
+            help: metadata.map(|m| m.help.clone()).unwrap_or_default(),
+            unit: metadata.map(|m| m.unit.clone()).unwrap_or_default(),
+            name,
+        };
+        for sample in series.samples {
+            builder.add_number(&family, &labels, sample.value, millis_to_nanos(sample.timestamp));
+        }
+    }
+    builder.build()
+}
+
+fn family_name(name: &str) -> &str {
+    COUNTER_SUFFIXES.iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(name)
+}
+
+fn metric_kind(name: &str, metric_type: Option<MetricType>) -> MetricKind {
+    match metric_type {
+        Some(MetricType::Counter) => MetricKind::Counter,
+        Some(MetricType::Gauge | MetricType::Gaugehistogram | MetricType::Info | MetricType::Stateset) => MetricKind::Gauge,
+        // without metadata the name tells what the series is, following the prometheus naming conventions.
+        _ if COUNTER_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) => MetricKind::Counter,
+        _ => MetricKind::Gauge,
+    }
+}
+
+pub(crate) fn millis_to_nanos(millis: i64) -> u64 {
+    unit_to_nanos(millis, 1_000_000)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MetricKind {
+    Gauge,
+    Counter,
+    Histogram,
+}
+
+pub(crate) struct MetricFamily {
+    pub name: String,
+    pub kind: MetricKind,
+    pub help: String,
+    pub unit: String,
+}
+
+// the job and instance identify the resource, the name is dropped as it is the metric name.
+fn split_labels(labels: &[(String, String)]) -> ((String, String), Vec<KeyValue>) {
+    let mut job = String::new();
+    let mut instance = String::new();
+    let mut attributes = Vec::new();
+    for (name, value) in labels {
+        match name.as_str() {
+            NAME_LABEL => {},
+            JOB_LABEL => job = value.clone(),
+            INSTANCE_LABEL => instance = value.clone(),
+            _ => attributes.push(string_attribute(name, value.clone())),
+        }
+    }
+    ((job, instance), attributes)
+}
+
+/// Collects prometheus samples into OTLP metrics. The `job` and `instance` labels identify the resource,
+/// the other labels become attributes of the data points.
+#[derive(Default)]
+pub(crate) struct MetricsBuilder {
+    resources: BTreeMap<(String, String), BTreeMap<String, Metric>>,
+}
+
+impl MetricsBuilder {
+    pub fn add_number(&mut self, family: &MetricFamily, labels: &[(String, String)], value: f64, time_unix_nano: u64) {
+        let (resource, attributes) = split_labels(labels);
+        let data_point = NumberDataPoint {
+            attributes,
+            time_unix_nano,
+            value: Some(number_data_point::Value::AsDouble(value)),
+            ..Default::default()
+        };
+        match &mut self.metric(resource, family).data {
+            Some(metric::Data::Gauge(gauge)) => gauge.data_points.push(data_point),
+            Some(metric::Data::Sum(sum)) => sum.data_points.push(data_point),
+            _ => {},
+        }
+    }
+
+    pub fn add_histogram(&mut self, family: &MetricFamily, labels: &[(String, String)], data_point: HistogramDataPoint) {
+        let (resource, attributes) = split_labels(labels);
+        if let Some(metric::Data::Histogram(histogram)) = &mut self.metric(resource, family).data {
+            histogram.data_points.push(HistogramDataPoint { attributes, ..data_point });
+        }
+    }
+
+    fn metric(&mut self, resource: (String, String), family: &MetricFamily) -> &mut Metric {
+        self.resources.entry(resource).or_default()
+            .entry(family.name.clone())
+            .or_insert_with(|| Metric {
+                name: family.name.clone(),
+                description: family.help.clone(),
+                unit: family.unit.clone(),
+                data: Some(match family.kind {
+                    MetricKind::Gauge => metric::Data::Gauge(Gauge::default()),
+                    MetricKind::Counter => metric::Data::Sum(Sum {
+                        aggregation_temporality: AggregationTemporality::Cumulative.into(),
+                        is_monotonic: true,
+                        ..Default::default()
+                    }),
+                    MetricKind::Histogram => metric::Data::Histogram(Histogram {
+                        aggregation_temporality: AggregationTemporality::Cumulative.into(),
+                        ..Default::default()
+                    }),
+                }),
+                ..Default::default()
+            })
+    }
+
+    pub fn build(self) -> ExportMetricsServiceRequest {
+        ExportMetricsServiceRequest {
+            resource_metrics: self.resources.into_iter().map(|((job, instance), metrics)| {
+                let attributes = if instance.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![string_attribute(SERVICE_INSTANCE_ID, instance)]
+                };
+                ResourceMetrics {
+                    resource: Some(service_resource(&job, attributes)),
+                    scope_metrics: vec![ScopeMetrics {
+                        metrics: metrics.into_values().collect(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }
+            }).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::metrics::{MetricDto, MetricType as MetricDtoType, NumberValue};
+
+    fn series(labels: &[(&str, &str)], value: f64) -> proto::TimeSeries {
+        proto::TimeSeries {
+            labels: labels.iter().map(|(name, value)| proto::Label { name: name.to_string(), value: value.to_string() }).collect(),
+            samples: vec![proto::Sample { value, timestamp: 1_700_000_000_000 }],
+        }
+    }
+
+    fn to_dtos(request: ExportMetricsServiceRequest) -> Vec<MetricDto> {
+        request.resource_metrics.into_iter().flat_map(|resource_metrics| {
+            let resource = resource_metrics.resource;
+            resource_metrics.scope_metrics.into_iter()
+                .flat_map(|scope_metrics| scope_metrics.metrics)
+                .map(|metric| MetricDto::from_otlp(metric, None, resource.as_ref()))
+                .collect::<Vec<_>>()
+        }).collect()
+    }
+
+    #[test]
+    fn test_write_request() {
+        let write_request = proto::WriteRequest {
+            timeseries: vec![
+                series(&[("__name__", "http_requests_total"), ("job", "api"), ("instance", "localhost:8080"), ("code", "200")], 10.0),
+                series(&[("__name__", "http_requests_total"), ("job", "api"), ("instance", "localhost:8080"), ("code", "500")], 2.0),
+                series(&[("__name__", "memory_bytes"), ("job", "api"), ("instance", "localhost:8080")], 512.0),
+                series(&[("__name__", "queue_size"), ("job", "worker")], 3.0),
+                series(&[("job", "unnamed")], 1.0),
+            ],
+            metadata: vec![proto::MetricMetadata {
+                r#type: MetricType::Gauge.into(),
+                metric_family_name: "queue_size".to_string(),
+                help: "Waiting jobs.".to_string(),
+                unit: "".to_string(),
+            }],
+        };
+
+        let dtos = to_dtos(to_otlp(write_request));
+        assert_eq!(3, dtos.len());
+
+        let requests = &dtos[0];
+        assert_eq!("http_requests_total", requests.name);
+        assert_eq!("api", requests.resource.service_name);
+        assert_eq!("localhost:8080", requests.resource.service_instance_id);
+        match &requests.data {
+            Some(MetricDtoType::Sum(sum)) => {
+                assert!(sum.is_monotonic);
+                assert_eq!(2, sum.data_points.len());
+                assert_eq!(Some(&"500".to_string()), sum.data_points[1].attributes.get("code"));
+                assert!(!sum.data_points[1].attributes.contains_key("job"));
+                assert!(matches!(sum.data_points[1].value, Some(NumberValue::Double(2.0))));
+            },
+            _ => panic!("Expected a sum"),
+        }
+
+        assert_eq!("memory_bytes", dtos[1].name);
+        assert!(matches!(dtos[1].data, Some(MetricDtoType::Gauge(_))));
+
+        assert_eq!("queue_size", dtos[2].name);
+        assert_eq!("worker", dtos[2].resource.service_name);
+        assert_eq!("Waiting jobs.", dtos[2].description);
+        assert!(matches!(dtos[2].data, Some(MetricDtoType::Gauge(_))));
+    }
+
+    #[test]
+    fn test_metric_kind() {
+        assert_eq!(MetricKind::Counter, metric_kind("requests_total", None));
+        assert_eq!(MetricKind::Counter, metric_kind("latency_seconds_bucket", Some(MetricType::Histogram)));
+        assert_eq!(MetricKind::Gauge, metric_kind("queue_bucket", Some(MetricType::Gaugehistogram)));
+        assert_eq!(MetricKind::Counter, metric_kind("restarts", Some(MetricType::Counter)));
+        assert_eq!(MetricKind::Gauge, metric_kind("temperature", None));
+        assert_eq!("latency_seconds", family_name("latency_seconds_sum"));
+    }
+
+    #[test]
+    fn test_millis_to_nanos() {
+        assert_eq!(1_500_000_000, millis_to_nanos(1_500));
+        assert_eq!(0, millis_to_nanos(-1));
+        assert_eq!(0, millis_to_nanos(i64::MAX));
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+// the series which belong to a family next to the one named like it.
+const FAMILY_SUFFIXES: [&str; 6] = ["_total", "_count", "_sum", "_bucket", "_created", "_info"];
+
+/// The label names and values in the order of the exposition.
+pub type Labels = Vec<(String, String)>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FamilyType {
+    Counter,
+    Gauge,
+    Histogram,
+    Summary,
+    Unknown,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: Labels,
+    pub value: f64,
+    /// Milliseconds since epoch, when the exposition has one.
+    pub timestamp: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct Family {
+    pub name: String,
+    pub family_type: FamilyType,
+    pub help: String,
+    pub unit: String,
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses the Prometheus text exposition format and OpenMetrics, which differs in the timestamps unit
+/// (seconds instead of milliseconds), the `# UNIT` line and the exemplars.
+// https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+// https://prometheus.io/docs/specs/om/open_metrics_spec/
+pub fn parse(text: &str, open_metrics: bool) -> Result<Vec<Family>, ParseError> {
+    let mut families: Vec<Family> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| ParseError { line: number + 1, message: message.to_string() };
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut parts = comment.trim_start().splitn(3, ' ');
+            let (keyword, name, rest) = (parts.next().unwrap_or_default(), parts.next(), parts.next().unwrap_or_default());
+            if keyword == "EOF" {
+                break;
+            }
+            let Some(name) = name else {
+                continue;
+            };
+            match keyword {
+                "HELP" => family(&mut families, &mut index, name).help = unescape(rest),
+                "UNIT" => family(&mut families, &mut index, name).unit = rest.to_string(),
+                "TYPE" => family(&mut families, &mut index, name).family_type = match rest.trim() {
+                    "counter" => FamilyType::Counter,
+                    "gauge" => FamilyType::Gauge,
+                    "histogram" => FamilyType::Histogram,
+                    "summary" => FamilyType::Summary,
+                    _ => FamilyType::Unknown,
+                },
+                // other comments are ignored.
+                _ => {},
+            }
+            continue;
+        }
+
+        let sample = parse_sample(line, open_metrics).map_err(error)?;
+        let family_name = if index.contains_key(&sample.name) {
+            sample.name.clone()
+        } else {
+            FAMILY_SUFFIXES.iter()
+                .filter_map(|suffix| sample.name.strip_suffix(suffix))
+                .find(|name| index.contains_key(*name))
+                .unwrap_or(&sample.name)
+                .to_string()
+        };
+        family(&mut families, &mut index, &family_name).samples.push(sample);
+    }
+    Ok(families)
+}
+
+fn family<'a>(families: &'a mut Vec<Family>, index: &mut HashMap<String, usize>, name: &str) -> &'a mut Family {
+    let position = *index.entry(name.to_string()).or_insert_with(|| {
+        families.push(Family {
+            name: name.to_string(),
+            family_type: FamilyType::Unknown,
+            help: String::new(),
+            unit: String::new(),
+            samples: Vec::new(),
+        });
+        families.len() - 1
+    });
+    &mut families[position]
+}
+
+fn parse_sample(line: &str, open_metrics: bool) -> Result<Sample, &'static str> {
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace()).ok_or("Sample without value")?;
+    let name = line[..name_end].to_string();
+    if name.is_empty() {
+        return Err("Sample without name");
+    }
+    let mut rest = &line[name_end..];
+    let mut labels = Vec::new();
+    if let Some(label_text) = rest.strip_prefix('{') {
+        let (parsed, remaining) = parse_labels(label_text)?;
+        labels = parsed;
+        rest = remaining;
+    }
+    // the exemplar of OpenMetrics follows the value and the timestamp after a `#`.
+    let rest = rest.split(" # ").next().unwrap_or_default();
+    let mut parts = rest.split_whitespace();
+    let value = parse_value(parts.next().ok_or("Sample without value")?)?;
+    let timestamp = match parts.next() {
+        Some(timestamp) if open_metrics => Some((timestamp.parse::<f64>().map_err(|_| "Invalid timestamp")? * 1000.0) as i64),
+        Some(timestamp) => Some(timestamp.parse::<i64>().map_err(|_| "Invalid timestamp")?),
+        None => None,
+    };
+    Ok(Sample { name, labels, value, timestamp })
+}
+
+// returns the labels and what follows the closing brace.
+pub fn parse_labels(text: &str) -> Result<(Labels, &str), &'static str> {
+    let mut labels = Vec::new();
+    let mut rest = text.trim_start();
+    loop {
+        if let Some(remaining) = rest.strip_prefix('}') {
+            return Ok((labels, remaining));
+        }
+        let equals = rest.find('=').ok_or("Label without value")?;
+        let name = rest[..equals].trim().to_string();
+        rest = rest[equals + 1..].trim_start().strip_prefix('"').ok_or("Label value is not quoted")?;
+
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, c)) => value.push(c),
+                    None => return Err("Unterminated label value"),
+                },
+                Some((i, '"')) => break i,
+                Some((_, c)) => value.push(c),
+                None => return Err("Unterminated label value"),
+            }
+        };
+        labels.push((name, value));
+        rest = rest[end + 1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+}
+
+fn parse_value(value: &str) -> Result<f64, &'static str> {
+    match value {
+        "+Inf" | "Inf" => Ok(f64::INFINITY),
+        "-Inf" => Ok(f64::NEG_INFINITY),
+        "NaN" => Ok(f64::NAN),
+        _ => value.parse::<f64>().map_err(|_| "Invalid value"),
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("\\n", "\n").replace("\\\\", "\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"
+# HELP http_requests_total The total number of HTTP requests.
+# TYPE http_requests_total counter
+http_requests_total{method="post",code="200"} 1027 1395066363000
+http_requests_total{method="post",code="400"}    3 1395066363000
+
+# Minimalistic line:
+metric_without_timestamp_and_labels 12.47
+
+# A histogram, which has a pretty complex representation in the text format:
+# HELP http_request_duration_seconds A histogram of the request duration.
+# TYPE http_request_duration_seconds histogram
+http_request_duration_seconds_bucket{le="0.05"} 24054
+http_request_duration_seconds_bucket{le="+Inf"} 144320
+http_request_duration_seconds_sum 53423
+http_request_duration_seconds_count 144320
+
+# TYPE rpc_duration_seconds summary
+rpc_duration_seconds{quantile="0.5"} 4773
+rpc_duration_seconds_sum 1.7560473e+07
+rpc_duration_seconds_count 2693
+
+escaped{path="C:\\DIR\\",text="with \"quotes\"\n"} -Inf
+"#;
+
+    #[test]
+    fn test_parse() {
+        let families = parse(TEXT, false).unwrap();
+        let names = families.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["http_requests_total", "metric_without_timestamp_and_labels", "http_request_duration_seconds", "rpc_duration_seconds", "escaped"], names);
+
+        let requests = &families[0];
+        assert_eq!(FamilyType::Counter, requests.family_type);
+        assert_eq!("The total number of HTTP requests.", requests.help);
+        assert_eq!(Sample {
+            name: "http_requests_total".to_string(),
+            labels: vec![("method".to_string(), "post".to_string()), ("code".to_string(), "400".to_string())],
+            value: 3.0,
+            timestamp: Some(1395066363000),
+        }, requests.samples[1]);
+
+        assert_eq!(FamilyType::Unknown, families[1].family_type);
+        assert_eq!(None, families[1].samples[0].timestamp);
+
+        let histogram = &families[2];
+        assert_eq!(FamilyType::Histogram, histogram.family_type);
+        assert_eq!(4, histogram.samples.len());
+        assert_eq!(("le".to_string(), "+Inf".to_string()), histogram.samples[1].labels[0]);
+
+        assert_eq!(FamilyType::Summary, families[3].family_type);
+        assert_eq!(3, families[3].samples.len());
+
+        let escaped = &families[4].samples[0];
+        assert_eq!(("path".to_string(), "C:\\DIR\\".to_string()), escaped.labels[0]);
+        assert_eq!(("text".to_string(), "with \"quotes\"\n".to_string()), escaped.labels[1]);
+        assert_eq!(f64::NEG_INFINITY, escaped.value);
+    }
+
+    #[test]
+    fn test_parse_open_metrics() {
+        let text = "# TYPE jobs counter\n# UNIT jobs seconds\njobs_total{a=\"b\"} 2 1.5 # {trace_id=\"1\"} 1\n# EOF\nignored 1\n";
+        let families = parse(text, true).unwrap();
+        assert_eq!(1, families.len());
+        assert_eq!("seconds", families[0].unit);
+        assert_eq!("jobs_total", families[0].samples[0].name);
+        assert_eq!(2.0, families[0].samples[0].value);
+        assert_eq!(Some(1500), families[0].samples[0].timestamp);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = parse("ok 1\nbroken{a=\"b} 1\n", false).unwrap_err();
+        assert_eq!("line 2: Unterminated label value", error.to_string());
+        assert!(parse("no_value\n", false).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+use futures::future::join_all;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use tokio::time::MissedTickBehavior;
+use crate::app_state::AppState;
+use crate::config::ScrapeConfig;
+use crate::opentelemetry::proto::metrics::v1::HistogramDataPoint;
+use crate::receivers::prometheus::{millis_to_nanos, MetricFamily, MetricKind, MetricsBuilder, INSTANCE_LABEL, JOB_LABEL};
+use crate::receivers::prometheus_text::{parse, Family, FamilyType, Labels, Sample};
+use crate::server::shutdown_signal;
+
+// prefer OpenMetrics, like prometheus does.
+const ACCEPT_HEADER: &str = "application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5";
+const OPEN_METRICS_CONTENT_TYPE: &str = "application/openmetrics-text";
+const BUCKET_LABEL: &str = "le";
+// reports whether the last scrape of the target worked, like the one prometheus adds.
+const UP_METRIC: &str = "up";
+
+#[derive(Debug, PartialEq)]
+struct ScrapeTarget {
+    job: String,
+    instance: String,
+    url: String,
+}
+
+impl ScrapeTarget {
+    /// Parses `url` or `job=url`, without a job the instance (host:port) is used as job.
+    fn parse(target: &str) -> Result<Self, String> {
+        let (job, url) = match target.split_once('=') {
+            Some((job, url)) if !job.contains(['/', ':']) => (Some(job.trim()), url.trim()),
+            _ => (None, target),
+        };
+        let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid scrape target '{}': {}", target, e))?;
+        let host = parsed.host_str().ok_or_else(|| format!("Scrape target '{}' has no host", target))?;
+        let instance = match parsed.port_or_known_default() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        Ok(ScrapeTarget {
+            job: job.map(str::to_string).unwrap_or_else(|| instance.clone()),
+            instance,
+            url: url.to_string(),
+        })
+    }
+}
+
+/// Pulls the metrics of the configured Prometheus endpoints on an interval.
+pub async fn init_scrape(state: AppState, config: ScrapeConfig) -> Result<(), Box<dyn Error>> {
+    let targets = config.targets.iter()
+        .map(|target| ScrapeTarget::parse(target))
+        .collect::<Result<Vec<_>, _>>()?;
+    let client = reqwest::Client::builder().timeout(config.interval).build()?;
+    println!("Scraping {} targets every {}s", targets.len(), config.interval.as_secs());
+
+    let scrape_loop = async {
+        let mut interval = tokio::time::interval(config.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut up = vec![true; targets.len()];
+        loop {
+            interval.tick().await;
+            up = join_all(targets.iter().zip(up)
+                .map(|(target, was_up)| scrape(&state, &client, target, was_up))).await;
+        }
+    };
+    tokio::select! {
+        _ = scrape_loop => {},
+        _ = shutdown_signal() => {},
+    }
+    Ok(())
+}
+
+// returns whether the target was scraped, the failure is only logged when the target was up before.
+async fn scrape(state: &AppState, client: &reqwest::Client, target: &ScrapeTarget, was_up: bool) -> bool {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+    let mut builder = MetricsBuilder::default();
+    let up = match fetch(client, target).await {
+        Ok(families) => {
+            add_families(&mut builder, families, target, now);
+            true
+        },
+        Err(e) => {
+            if was_up {
+                println!("Unable to scrape {}: {}", target.url, e);
+            }
+            false
+        }
+    };
+    let up_family = MetricFamily {
+        name: UP_METRIC.to_string(),
+        kind: MetricKind::Gauge,
+        help: "Whether the last scrape of the target succeeded.".to_string(),
+        unit: String::new(),
+    };
+    builder.add_number(&up_family, &target_labels(Vec::new(), target), if up { 1.0 } else { 0.0 }, now);
+    state.request_processor.process_metrics(builder.build()).await;
+    up
+}
+
+async fn fetch(client: &reqwest::Client, target: &ScrapeTarget) -> Result<Vec<Family>, Box<dyn Error>> {
+    let response = client.get(&target.url)
+        .header(ACCEPT, ACCEPT_HEADER)
+        .send()
+        .await?
+        .error_for_status()?;
+    let open_metrics = response.headers().get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with(OPEN_METRICS_CONTENT_TYPE));
+    let text = response.text().await?;
+    Ok(parse(&text, open_metrics)?)
+}
+
+fn add_families(builder: &mut MetricsBuilder, families: Vec<Family>, target: &ScrapeTarget, now: u64) {
+    for family in families {
+        let metric_family = |name: &str, kind: MetricKind| MetricFamily {
+            name: name.to_string(),
+            kind,
+            help: family.help.clone(),
+            unit: family.unit.clone(),
+        };
+        if family.family_type == FamilyType::Histogram {
+            for (labels, histogram) in histogram_data_points(family.samples, now) {
+                builder.add_histogram(&metric_family(&family.name, MetricKind::Histogram), &target_labels(labels, target), histogram);
+            }
+            continue;
+        }
+        for sample in &family.samples {
+            // the creation time of counters isn't shown.
+            if sample.name.ends_with("_created") {
+                continue;
+            }
+            let kind = match family.family_type {
+                FamilyType::Counter => MetricKind::Counter,
+                // the quantiles are gauges, the sum and count of the observations are counters.
+                FamilyType::Summary if sample.name != family.name => MetricKind::Counter,
+                _ => MetricKind::Gauge,
+            };
+            let time = sample.timestamp.map(millis_to_nanos).unwrap_or(now);
+            builder.add_number(&metric_family(&sample.name, kind), &target_labels(sample.labels.clone(), target), sample.value, time);
+        }
+    }
+}
+
+// the target labels win, the exposed ones are kept with the prefix prometheus uses for them.
+fn target_labels(labels: Labels, target: &ScrapeTarget) -> Labels {
+    let mut labels = labels.into_iter()
+        .map(|(name, value)| match name.as_str() {
+            JOB_LABEL | INSTANCE_LABEL => (format!("exported_{}", name), value),
+            _ => (name, value),
+        })
+        .collect::<Vec<_>>();
+    labels.push((JOB_LABEL.to_string(), target.job.clone()));
+    labels.push((INSTANCE_LABEL.to_string(), target.instance.clone()));
+    labels
+}
+
+#[derive(Default)]
+struct HistogramSeries {
+    // upper bound and cumulative count of every bucket.
+    buckets: Vec<(f64, f64)>,
+    sum: Option<f64>,
+    count: Option<f64>,
+    timestamp: Option<i64>,
+}
+
+/// Joins the `_bucket`, `_sum` and `_count` series of a histogram by their labels.
+fn histogram_data_points(samples: Vec<Sample>, now: u64) -> Vec<(Labels, HistogramDataPoint)> {
+    let mut series: BTreeMap<Labels, HistogramSeries> = BTreeMap::new();
+    for sample in samples {
+        let mut labels = sample.labels;
+        let bound = labels.iter()
+            .position(|(name, _)| name == BUCKET_LABEL)
+            .map(|index| labels.remove(index).1);
+        let entry = series.entry(labels).or_default();
+        entry.timestamp = entry.timestamp.or(sample.timestamp);
+        if sample.name.ends_with("_bucket") {
+            if let Some(bound) = bound.and_then(|b| b.parse::<f64>().ok()) {
+                entry.buckets.push((bound, sample.value));
+            }
+        } else if sample.name.ends_with("_sum") {
+            entry.sum = Some(sample.value);
+        } else if sample.name.ends_with("_count") {
+            entry.count = Some(sample.value);
+        }
+    }
+
+    series.into_iter().map(|(labels, mut histogram)| {
+        histogram.buckets.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut explicit_bounds = Vec::new();
+        let mut bucket_counts = Vec::new();
+        let mut previous = 0.0;
+        for (bound, cumulative) in histogram.buckets.iter().filter(|(bound, _)| bound.is_finite()) {
+            explicit_bounds.push(*bound);
+            bucket_counts.push((cumulative - previous).max(0.0) as u64);
+            previous = *cumulative;
+        }
+        let count = histogram.count
+            .or_else(|| histogram.buckets.last().map(|(_, cumulative)| *cumulative))
+            .unwrap_or(previous);
+        // the +Inf bucket holds what is above the last bound.
+        bucket_counts.push((count - previous).max(0.0) as u64);
+        let data_point = HistogramDataPoint {
+            time_unix_nano: histogram.timestamp.map(millis_to_nanos).unwrap_or(now),
+            count: count as u64,
+            sum: histogram.sum,
+            bucket_counts,
+            explicit_bounds,
+            ..Default::default()
+        };
+        (labels, data_point)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::metrics::{MetricDto, MetricType};
+    use crate::opentelemetry::proto::collector::metrics::v1::ExportMetricsServiceRequest;
+
+    const TEXT: &str = r#"
+# HELP http_requests_total The total number of HTTP requests.
+# TYPE http_requests_total counter
+http_requests_total{code="200",job="exported"} 1027
+http_requests_created{code="200"} 1395066363
+# TYPE temperature gauge
+temperature 21.5 1395066363000
+# TYPE latency_seconds histogram
+latency_seconds_bucket{path="/",le="0.1"} 2
+latency_seconds_bucket{path="/",le="1"} 5
+latency_seconds_bucket{path="/",le="+Inf"} 6
+latency_seconds_sum{path="/"} 3.5
+latency_seconds_count{path="/"} 6
+# TYPE rpc_seconds summary
+rpc_seconds{quantile="0.5"} 0.2
+rpc_seconds_sum 10
+rpc_seconds_count 40
+"#;
+
+    fn to_otlp(families: Vec<Family>, target: &ScrapeTarget, now: u64) -> ExportMetricsServiceRequest {
+        let mut builder = MetricsBuilder::default();
+        add_families(&mut builder, families, target, now);
+        builder.build()
+    }
+
+    fn to_dtos(request: ExportMetricsServiceRequest) -> Vec<MetricDto> {
+        request.resource_metrics.into_iter().flat_map(|resource_metrics| {
+            let resource = resource_metrics.resource;
+            resource_metrics.scope_metrics.into_iter()
+                .flat_map(|scope_metrics| scope_metrics.metrics)
+                .map(|metric| MetricDto::from_otlp(metric, None, resource.as_ref()))
+                .collect::<Vec<_>>()
+        }).collect()
+    }
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(ScrapeTarget {
+            job: "localhost:9100".to_string(),
+            instance: "localhost:9100".to_string(),
+            url: "http://localhost:9100/metrics".to_string(),
+        }, ScrapeTarget::parse("http://localhost:9100/metrics").unwrap());
+        assert_eq!(ScrapeTarget {
+            job: "api".to_string(),
+            instance: "example.com:443".to_string(),
+            url: "https://example.com/metrics?format=text".to_string(),
+        }, ScrapeTarget::parse("api=https://example.com/metrics?format=text").unwrap());
+        assert!(ScrapeTarget::parse("localhost").is_err());
+    }
+
+    #[test]
+    fn test_to_otlp() {
+        let target = ScrapeTarget::parse("api=http://localhost:8080/metrics").unwrap();
+        let families = parse(TEXT, false).unwrap();
+        let dtos = to_dtos(to_otlp(families, &target, 42));
+        let names = dtos.iter().map(|dto| dto.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["http_requests_total", "latency_seconds", "rpc_seconds", "rpc_seconds_count", "rpc_seconds_sum", "temperature"], names);
+        assert!(dtos.iter().all(|dto| dto.resource.service_name == "api" && dto.resource.service_instance_id == "localhost:8080"));
+
+        match &dtos[0].data {
+            Some(MetricType::Sum(sum)) => {
+                assert!(sum.is_monotonic);
+                assert_eq!(1, sum.data_points.len());
+                assert_eq!(Some(&"exported".to_string()), sum.data_points[0].attributes.get("exported_job"));
+            },
+            _ => panic!("Expected a sum"),
+        }
+        match &dtos[1].data {
+            Some(MetricType::Histogram(histogram)) => {
+                let data_point = &histogram.data_points[0];
+                assert_eq!(6, data_point.count);
+                assert_eq!(Some(3.5), data_point.sum);
+                assert_eq!(vec![0.1, 1.0], data_point.explicit_bounds);
+                assert_eq!(vec![2, 3, 1], data_point.bucket_counts);
+                assert_eq!(Some(&"/".to_string()), data_point.attributes.get("path"));
+            },
+            _ => panic!("Expected a histogram"),
+        }
+        assert!(matches!(dtos[2].data, Some(MetricType::Gauge(_))));
+        assert!(matches!(dtos[3].data, Some(MetricType::Sum(_))));
+        match &dtos[5].data {
+            Some(MetricType::Gauge(gauge)) => assert_eq!("1395066363000000000", serde_json::to_value(&gauge.data_points[0].time_unix_nano).unwrap()),
+            _ => panic!("Expected a gauge"),
+        }
+    }
+}
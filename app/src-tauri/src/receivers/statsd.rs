@@ -0,0 +1,406 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use chrono::Utc;
+use opentelemetry_semantic_conventions::resource::{DEPLOYMENT_ENVIRONMENT_NAME, SERVICE_VERSION};
+use tokio::net::UdpSocket;
+use tokio::time::{interval_at, Instant};
+use crate::app_state::AppState;
+use crate::config::StatsdConfig;
+use crate::opentelemetry::proto::collector::metrics::v1::ExportMetricsServiceRequest;
+use crate::opentelemetry::proto::metrics::v1::{metric, number_data_point, AggregationTemporality, Gauge, Histogram, HistogramDataPoint, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum};
+use crate::receivers::{service_resource, string_attribute};
+use crate::server::shutdown_signal;
+
+const MAX_PACKET_SIZE: usize = 65_535;
+const DEFAULT_SERVICE_NAME: &str = "statsd";
+// the DogStatsD unified service tags, which describe the service instead of the series.
+const SERVICE_TAG: &str = "service";
+const VERSION_TAG: &str = "version";
+const ENV_TAG: &str = "env";
+const TIMER_UNIT: &str = "ms";
+// a gauge which isn't updated for this many flushes is forgotten, its relative updates start again from 0.
+const MAX_IDLE_GAUGE_FLUSHES: u32 = 60;
+// the default explicit bucket boundaries of the OpenTelemetry SDKs.
+const HISTOGRAM_BOUNDS: [f64; 15] = [0.0, 5.0, 10.0, 25.0, 50.0, 75.0, 100.0, 250.0, 500.0, 750.0, 1000.0, 2500.0, 5000.0, 7500.0, 10000.0];
+
+type Tags = Vec<(String, String)>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MetricType {
+    Counter,
+    Gauge,
+    Timer,
+    Histogram,
+    Distribution,
+    Set,
+}
+
+#[derive(Debug, PartialEq)]
+struct StatsdLine<'a> {
+    name: &'a str,
+    values: Vec<&'a str>,
+    metric_type: MetricType,
+    sample_rate: f64,
+    tags: Tags,
+}
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
+struct SeriesKey {
+    name: String,
+    tags: Tags,
+}
+
+#[derive(Debug, Default)]
+struct GaugeState {
+    value: f64,
+    // how many flushes ago the gauge was last updated.
+    idle_flushes: u32,
+}
+
+#[derive(Debug)]
+struct HistogramState {
+    unit: &'static str,
+    count: f64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    bucket_counts: Vec<f64>,
+}
+
+/// Aggregates the StatsD lines between two flushes: counters into delta sums, gauges into their last value,
+/// timers, histograms and distributions into delta histograms and sets into the count of their unique values.
+#[derive(Debug, Default)]
+struct Aggregator {
+    counters: BTreeMap<SeriesKey, f64>,
+    // the gauges keep their value between flushes for the relative updates, only the updated ones are flushed.
+    gauges: BTreeMap<SeriesKey, GaugeState>,
+    histograms: BTreeMap<SeriesKey, HistogramState>,
+    sets: BTreeMap<SeriesKey, BTreeSet<String>>,
+}
+
+/// Listens for StatsD and DogStatsD lines on udp and publishes their aggregation on every flush interval.
+// https://github.com/statsd/statsd/blob/master/docs/metric_types.md
+// https://docs.datadoghq.com/developers/dogstatsd/datagram_shell/
+pub async fn init_statsd(state: AppState, config: StatsdConfig) -> Result<(), Box<dyn Error>> {
+    let socket = UdpSocket::bind(&config.address).await?;
+    println!("StatsD receiver listening on udp://{}", config.address);
+    let mut aggregator = Aggregator::default();
+    let mut buffer = vec![0; MAX_PACKET_SIZE];
+    let mut flush = interval_at(Instant::now() + config.flush_interval, config.flush_interval);
+    let mut start_time_unix_nano = now_unix_nano();
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            r = socket.recv_from(&mut buffer) => {
+                // e.g. an ICMP port unreachable of a previous send on windows, the next packets still arrive.
+                match r {
+                    Ok((size, _)) => aggregator.add_packet(&String::from_utf8_lossy(&buffer[..size])),
+                    Err(e) => println!("StatsD receive failed: {}", e),
+                }
+            },
+            _ = flush.tick() => {
+                let time_unix_nano = now_unix_nano();
+                if let Some(request) = aggregator.flush(start_time_unix_nano, time_unix_nano) {
+                    state.request_processor.process_metrics(request).await;
+                }
+                start_time_unix_nano = time_unix_nano;
+            },
+            _ = &mut shutdown => return Ok(()),
+        }
+    }
+}
+
+fn now_unix_nano() -> u64 {
+    Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64
+}
+
+// <name>:<value>[:<value>...]|<type>[|@<sample rate>][|#<tag>:<value>,<tag>][|...]
+fn parse_line(line: &str) -> Option<StatsdLine<'_>> {
+    // the DogStatsD events and service checks aren't metrics.
+    if line.starts_with("_e{") || line.starts_with("_sc|") {
+        return None;
+    }
+    let mut sections = line.split('|');
+    let (name, values) = sections.next()?.split_once(':')?;
+    let metric_type = match sections.next()? {
+        "c" => MetricType::Counter,
+        "g" => MetricType::Gauge,
+        "ms" => MetricType::Timer,
+        "h" => MetricType::Histogram,
+        "d" => MetricType::Distribution,
+        "s" => MetricType::Set,
+        _ => return None,
+    };
+    let mut sample_rate = 1.0;
+    let mut tags = Vec::new();
+    for section in sections {
+        if let Some(rate) = section.strip_prefix('@') {
+            sample_rate = rate.parse::<f64>().ok().filter(|rate| *rate > 0.0 && *rate <= 1.0)?;
+        } else if let Some(tag_list) = section.strip_prefix('#') {
+            tags.extend(tag_list.split(',')
+                .filter(|tag| !tag.is_empty())
+                .map(|tag| match tag.split_once(':') {
+                    Some((key, value)) => (key.to_string(), value.to_string()),
+                    None => (tag.to_string(), String::new()),
+                }));
+        }
+        // the container id and timestamp sections of DogStatsD are ignored.
+    }
+    let values = if metric_type == MetricType::Set { vec![values] } else { values.split(':').collect() };
+    if name.is_empty() {
+        return None;
+    }
+    Some(StatsdLine { name, values, metric_type, sample_rate, tags })
+}
+
+impl Aggregator {
+    fn add_packet(&mut self, packet: &str) {
+        for line in packet.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match parse_line(line) {
+                Some(line) => self.add(line),
+                None => println!("Invalid StatsD line: {}", line),
+            }
+        }
+    }
+
+    fn add(&mut self, line: StatsdLine) {
+        let mut tags = line.tags;
+        tags.sort();
+        let key = SeriesKey { name: line.name.to_string(), tags };
+        for value in line.values {
+            match line.metric_type {
+                MetricType::Set => {
+                    self.sets.entry(key.clone()).or_default().insert(value.to_string());
+                },
+                MetricType::Gauge => {
+                    let Ok(number) = value.parse::<f64>() else { continue };
+                    let gauge = self.gauges.entry(key.clone()).or_default();
+                    // a sign makes the gauge relative to its previous value.
+                    if value.starts_with(['+', '-']) {
+                        gauge.value += number;
+                    } else {
+                        gauge.value = number;
+                    }
+                    gauge.idle_flushes = 0;
+                },
+                MetricType::Counter => {
+                    let Ok(number) = value.parse::<f64>() else { continue };
+                    *self.counters.entry(key.clone()).or_default() += number / line.sample_rate;
+                },
+                MetricType::Timer | MetricType::Histogram | MetricType::Distribution => {
+                    let Ok(number) = value.parse::<f64>() else { continue };
+                    let unit = if line.metric_type == MetricType::Timer { TIMER_UNIT } else { "" };
+                    let histogram = self.histograms.entry(key.clone()).or_insert_with(|| HistogramState {
+                        unit,
+                        count: 0.0,
+                        sum: 0.0,
+                        min: f64::INFINITY,
+                        max: f64::NEG_INFINITY,
+                        bucket_counts: vec![0.0; HISTOGRAM_BOUNDS.len() + 1],
+                    });
+                    // a sampled value stands for the values which weren't sent.
+                    let weight = 1.0 / line.sample_rate;
+                    histogram.count += weight;
+                    histogram.sum += number * weight;
+                    histogram.min = histogram.min.min(number);
+                    histogram.max = histogram.max.max(number);
+                    let bucket = HISTOGRAM_BOUNDS.iter().position(|bound| number <= *bound).unwrap_or(HISTOGRAM_BOUNDS.len());
+                    histogram.bucket_counts[bucket] += weight;
+                },
+            }
+        }
+    }
+
+    // returns the metrics aggregated since the previous flush, `None` when nothing was received.
+    fn flush(&mut self, start_time_unix_nano: u64, time_unix_nano: u64) -> Option<ExportMetricsServiceRequest> {
+        let mut builder = MetricsBuilder::default();
+        for (key, value) in std::mem::take(&mut self.counters) {
+            let point = number_point(&key, value, start_time_unix_nano, time_unix_nano);
+            builder.metric(&key, "", || metric::Data::Sum(Sum {
+                aggregation_temporality: AggregationTemporality::Delta.into(),
+                is_monotonic: true,
+                ..Default::default()
+            }), |data| if let metric::Data::Sum(sum) = data { sum.data_points.push(point) });
+        }
+        for (key, state) in self.gauges.iter().filter(|(_, state)| state.idle_flushes == 0) {
+            let point = number_point(key, state.value, 0, time_unix_nano);
+            builder.metric(key, "", || metric::Data::Gauge(Gauge::default()),
+                           |data| if let metric::Data::Gauge(gauge) = data { gauge.data_points.push(point) });
+        }
+        self.gauges.retain(|_, state| {
+            state.idle_flushes += 1;
+            state.idle_flushes <= MAX_IDLE_GAUGE_FLUSHES
+        });
+        for (key, values) in std::mem::take(&mut self.sets) {
+            let point = number_point(&key, values.len() as f64, 0, time_unix_nano);
+            builder.metric(&key, "", || metric::Data::Gauge(Gauge::default()),
+                           |data| if let metric::Data::Gauge(gauge) = data { gauge.data_points.push(point) });
+        }
+        for (key, histogram) in std::mem::take(&mut self.histograms) {
+            let point = HistogramDataPoint {
+                attributes: attributes(&key.tags),
+                start_time_unix_nano,
+                time_unix_nano,
+                count: histogram.count.round() as u64,
+                sum: Some(histogram.sum),
+                bucket_counts: histogram.bucket_counts.iter().map(|count| count.round() as u64).collect(),
+                explicit_bounds: HISTOGRAM_BOUNDS.to_vec(),
+                min: Some(histogram.min),
+                max: Some(histogram.max),
+                ..Default::default()
+            };
+            builder.metric(&key, histogram.unit, || metric::Data::Histogram(Histogram {
+                aggregation_temporality: AggregationTemporality::Delta.into(),
+                ..Default::default()
+            }), |data| if let metric::Data::Histogram(histogram) = data { histogram.data_points.push(point) });
+        }
+        builder.build()
+    }
+}
+
+fn number_point(key: &SeriesKey, value: f64, start_time_unix_nano: u64, time_unix_nano: u64) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes: attributes(&key.tags),
+        start_time_unix_nano,
+        time_unix_nano,
+        value: Some(number_data_point::Value::AsDouble(value)),
+        ..Default::default()
+    }
+}
+
+fn attributes(tags: &[(String, String)]) -> Vec<crate::opentelemetry::proto::common::v1::KeyValue> {
+    tags.iter()
+        .filter(|(key, _)| ![SERVICE_TAG, VERSION_TAG, ENV_TAG].contains(&key.as_str()))
+        .map(|(key, value)| string_attribute(key, value))
+        .collect()
+}
+
+// the service, version and env tags of every series.
+fn resource_tags(tags: &[(String, String)]) -> Tags {
+    tags.iter()
+        .filter(|(key, _)| [SERVICE_TAG, VERSION_TAG, ENV_TAG].contains(&key.as_str()))
+        .cloned()
+        .collect()
+}
+
+#[derive(Default)]
+struct MetricsBuilder {
+    resources: BTreeMap<Tags, BTreeMap<String, Metric>>,
+}
+
+impl MetricsBuilder {
+    fn metric(&mut self, key: &SeriesKey, unit: &str, data: impl FnOnce() -> metric::Data, add: impl FnOnce(&mut metric::Data)) {
+        let metric = self.resources.entry(resource_tags(&key.tags)).or_default()
+            .entry(key.name.clone())
+            .or_insert_with(|| Metric {
+                name: key.name.clone(),
+                unit: unit.to_string(),
+                data: Some(data()),
+                ..Default::default()
+            });
+        if let Some(data) = &mut metric.data {
+            add(data);
+        }
+    }
+
+    fn build(self) -> Option<ExportMetricsServiceRequest> {
+        if self.resources.is_empty() {
+            return None;
+        }
+        Some(ExportMetricsServiceRequest {
+            resource_metrics: self.resources.into_iter().map(|(tags, metrics)| {
+                let tag = |name: &str| tags.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+                let mut attributes = Vec::new();
+                if let Some(version) = tag(VERSION_TAG) {
+                    attributes.push(string_attribute(SERVICE_VERSION, version));
+                }
+                if let Some(env) = tag(ENV_TAG) {
+                    attributes.push(string_attribute(DEPLOYMENT_ENVIRONMENT_NAME, env));
+                }
+                ResourceMetrics {
+                    resource: Some(service_resource(tag(SERVICE_TAG).unwrap_or(DEFAULT_SERVICE_NAME), attributes)),
+                    scope_metrics: vec![ScopeMetrics {
+                        metrics: metrics.into_values().collect(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }
+            }).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::metrics::{MetricDto, MetricType as MetricDtoType, NumberValue};
+
+    fn to_dtos(request: ExportMetricsServiceRequest) -> Vec<MetricDto> {
+        request.resource_metrics.into_iter()
+            .flat_map(|r| {
+                let resource = r.resource;
+                r.scope_metrics.into_iter()
+                    .flat_map(|s| s.metrics)
+                    .map(|metric| MetricDto::from_otlp(metric, None, resource.as_ref()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(Some(StatsdLine {
+            name: "page.views",
+            values: vec!["1", "2"],
+            metric_type: MetricType::Counter,
+            sample_rate: 0.5,
+            tags: vec![("env".to_string(), "dev".to_string()), ("beta".to_string(), String::new())],
+        }), parse_line("page.views:1:2|c|@0.5|#env:dev,beta|c:83c3b2"));
+        assert_eq!(vec!["a:b"], parse_line("users:a:b|s").unwrap().values);
+        assert_eq!(None, parse_line("_e{5,4}:title|text"));
+        assert_eq!(None, parse_line("no.type:1"));
+        assert_eq!(None, parse_line("bad.rate:1|c|@2"));
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut aggregator = Aggregator::default();
+        aggregator.add_packet("requests:1|c|#service:checkout,route:/\nrequests:1|c|@0.5|#route:/,service:checkout\n\
+            queue:10|g\nqueue:-3|g\nlatency:20|ms\nlatency:300|ms|#service:checkout\nusers:alice|s\nusers:bob|s\nusers:alice|s\n");
+        let dtos = to_dtos(aggregator.flush(1, 2).unwrap());
+
+        let requests = dtos.iter().find(|m| m.name == "requests").unwrap();
+        assert_eq!("checkout", requests.resource.service_name);
+        let Some(MetricDtoType::Sum(sum)) = &requests.data else { panic!("requests isn't a sum") };
+        assert_eq!(1, sum.data_points.len());
+        assert!(matches!(sum.data_points[0].value, Some(NumberValue::Double(v)) if v == 3.0));
+        assert_eq!("/", sum.data_points[0].attributes["route"]);
+
+        let queue = dtos.iter().find(|m| m.name == "queue").unwrap();
+        assert_eq!(DEFAULT_SERVICE_NAME, queue.resource.service_name);
+        let Some(MetricDtoType::Gauge(gauge)) = &queue.data else { panic!("queue isn't a gauge") };
+        assert!(matches!(gauge.data_points[0].value, Some(NumberValue::Double(v)) if v == 7.0));
+
+        let latencies = dtos.iter().filter(|m| m.name == "latency").collect::<Vec<_>>();
+        assert_eq!(2, latencies.len());
+        assert!(latencies.iter().all(|latency| latency.unit == TIMER_UNIT));
+
+        let users = dtos.iter().find(|m| m.name == "users").unwrap();
+        let Some(MetricDtoType::Gauge(gauge)) = &users.data else { panic!("users isn't a gauge") };
+        assert!(matches!(gauge.data_points[0].value, Some(NumberValue::Double(v)) if v == 2.0));
+
+        // counters start over, gauges keep their value but are only sent when updated.
+        assert!(aggregator.flush(2, 3).is_none());
+        aggregator.add_packet("queue:+1|g");
+        let dtos = to_dtos(aggregator.flush(3, 4).unwrap());
+        let Some(MetricDtoType::Gauge(gauge)) = &dtos[0].data else { panic!("queue isn't a gauge") };
+        assert!(matches!(gauge.data_points[0].value, Some(NumberValue::Double(v)) if v == 8.0));
+
+        // the gauges which aren't updated any more are forgotten.
+        for flush in 0..MAX_IDLE_GAUGE_FLUSHES as u64 {
+            assert!(aggregator.flush(4 + flush, 5 + flush).is_none());
+        }
+        assert!(aggregator.gauges.is_empty());
+    }
+}
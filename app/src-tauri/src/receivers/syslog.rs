@@ -0,0 +1,408 @@
+use std::error::Error;
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeZone, Utc};
+use opentelemetry_semantic_conventions::resource::{HOST_NAME, PROCESS_PID};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use crate::app_state::AppState;
+use crate::opentelemetry::proto::collector::logs::v1::ExportLogsServiceRequest;
+use crate::opentelemetry::proto::common::v1::{any_value, AnyValue};
+use crate::opentelemetry::proto::logs::v1::{LogRecord, ResourceLogs, ScopeLogs};
+use crate::receivers::{int_attribute, service_resource, string_attribute};
+use crate::server::shutdown_signal;
+
+// a message without priority is user.notice.
+const DEFAULT_PRIORITY: u8 = 13;
+const MAX_PRIORITY: u8 = 191;
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+// the octet count of the largest message.
+const MAX_LENGTH_DIGITS: usize = 5;
+const NIL: &str = "-";
+const DEFAULT_SERVICE_NAME: &str = "syslog";
+
+const FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv", "ftp",
+    "ntp", "security", "console", "solaris-cron", "local0", "local1", "local2", "local3", "local4", "local5", "local6", "local7",
+];
+// the syslog severities with the OpenTelemetry severity numbers they map to.
+// https://opentelemetry.io/docs/specs/otel/logs/data-model-appendix/#appendix-b-severitynumber-example-mappings
+const SEVERITIES: [(&str, i32); 8] = [
+    ("emerg", 21),
+    ("alert", 19),
+    ("crit", 18),
+    ("err", 17),
+    ("warning", 13),
+    ("notice", 10),
+    ("info", 9),
+    ("debug", 5),
+];
+
+#[derive(Debug, Default, PartialEq)]
+struct SyslogMessage {
+    priority: u8,
+    timestamp: Option<DateTime<Utc>>,
+    hostname: Option<String>,
+    app_name: Option<String>,
+    proc_id: Option<String>,
+    msg_id: Option<String>,
+    /// The parameters of the structured data elements, named `<sd-id>.<param-name>`.
+    structured_data: Vec<(String, String)>,
+    message: String,
+}
+
+/// Listens for syslog messages on udp and tcp on the same address. On tcp the messages are either prefixed by
+/// their length or terminated by a newline.
+// https://datatracker.ietf.org/doc/html/rfc6587#section-3.4
+pub async fn init_syslog(state: AppState, address: String) -> Result<(), Box<dyn Error>> {
+    let socket = UdpSocket::bind(&address).await?;
+    let listener = TcpListener::bind(&address).await?;
+    println!("Syslog receiver listening on udp://{0} and tcp://{0}", address);
+    tokio::select! {
+        r = receive_datagrams(&state, socket) => r,
+        r = accept_connections(&state, listener) => r,
+        _ = shutdown_signal() => Ok(()),
+    }
+}
+
+async fn receive_datagrams(state: &AppState, socket: UdpSocket) -> Result<(), Box<dyn Error>> {
+    let mut buffer = vec![0; MAX_MESSAGE_SIZE];
+    loop {
+        // a failed receive, e.g. after an ICMP error or without buffer space, doesn't stop the tcp side with it.
+        match socket.recv_from(&mut buffer).await {
+            Ok((size, _)) => process(state, &buffer[..size]).await,
+            Err(e) => println!("Syslog receive failed: {}", e),
+        }
+    }
+}
+
+async fn accept_connections(state: &AppState, listener: TcpListener) -> Result<(), Box<dyn Error>> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle_connection(state.clone(), stream));
+    }
+}
+
+async fn handle_connection(state: AppState, stream: TcpStream) {
+    let mut reader = BufReader::new(stream);
+    loop {
+        match read_frame(&mut reader).await {
+            Ok(Some(frame)) => process(&state, &frame).await,
+            Ok(None) => break,
+            Err(e) => {
+                println!("Syslog connection closed: {}", e);
+                break;
+            },
+        }
+    }
+}
+
+// reads the next message, `None` at the end of the stream.
+async fn read_frame(reader: &mut (impl AsyncBufRead + Unpin)) -> std::io::Result<Option<Vec<u8>>> {
+    let first = match reader.fill_buf().await?.first() {
+        Some(first) => *first,
+        None => return Ok(None),
+    };
+    let mut frame = Vec::new();
+    if first.is_ascii_digit() {
+        // octet counting: the length, a space and the message.
+        read_until_limited(reader, b' ', MAX_LENGTH_DIGITS + 1, &mut frame).await?;
+        let length = std::str::from_utf8(&frame).ok()
+            .and_then(|length| length.trim().parse::<usize>().ok())
+            .filter(|length| *length <= MAX_MESSAGE_SIZE)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid message length"))?;
+        frame = vec![0; length];
+        reader.read_exact(&mut frame).await?;
+    } else {
+        read_until_limited(reader, b'\n', MAX_MESSAGE_SIZE + 1, &mut frame).await?;
+    }
+    Ok(Some(frame))
+}
+
+// a client which never sends the delimiter would fill the memory, the connection is closed at the limit instead.
+async fn read_until_limited(reader: &mut (impl AsyncBufRead + Unpin), delimiter: u8, limit: usize, frame: &mut Vec<u8>) -> std::io::Result<()> {
+    reader.take(limit as u64).read_until(delimiter, frame).await?;
+    if frame.len() == limit && frame.last() != Some(&delimiter) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Message too long"));
+    }
+    Ok(())
+}
+
+async fn process(state: &AppState, bytes: &[u8]) {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim_end_matches(['\r', '\n', '\0']);
+    if text.is_empty() {
+        return;
+    }
+    let message = parse(text, Local::now());
+    state.request_processor.process_logs(to_otlp(message)).await;
+}
+
+fn parse(text: &str, now: DateTime<Local>) -> SyslogMessage {
+    let (priority, rest) = parse_priority(text);
+    match rest.strip_prefix("1 ") {
+        Some(rest) => parse_rfc5424(priority, rest),
+        None => parse_rfc3164(priority, rest, now),
+    }
+}
+
+fn parse_priority(text: &str) -> (u8, &str) {
+    text.strip_prefix('<')
+        .and_then(|rest| rest.split_once('>'))
+        .and_then(|(priority, rest)| priority.parse::<u8>().ok()
+            .filter(|priority| *priority <= MAX_PRIORITY)
+            .map(|priority| (priority, rest)))
+        .unwrap_or((DEFAULT_PRIORITY, text))
+}
+
+// VERSION SP TIMESTAMP SP HOSTNAME SP APP-NAME SP PROCID SP MSGID SP STRUCTURED-DATA [SP MSG]
+// https://datatracker.ietf.org/doc/html/rfc5424#section-6
+fn parse_rfc5424(priority: u8, text: &str) -> SyslogMessage {
+    let mut parts = text.splitn(6, ' ');
+    let mut field = || parts.next().filter(|value| *value != NIL).map(str::to_string);
+    let timestamp = field().and_then(|timestamp| DateTime::parse_from_rfc3339(&timestamp).ok()).map(|t| t.to_utc());
+    let (hostname, app_name, proc_id, msg_id) = (field(), field(), field(), field());
+    let rest = parts.next().unwrap_or_default();
+
+    let (structured_data, message) = match rest.strip_prefix(NIL) {
+        Some(message) => (Vec::new(), message),
+        None => parse_structured_data(rest),
+    };
+    let message = message.strip_prefix(' ').unwrap_or(message);
+    SyslogMessage {
+        priority,
+        timestamp,
+        hostname,
+        app_name,
+        proc_id,
+        msg_id,
+        structured_data,
+        message: message.strip_prefix('\u{feff}').unwrap_or(message).to_string(),
+    }
+}
+
+// returns the parameters of the `[id name="value" ...]` elements and what follows them.
+fn parse_structured_data(mut text: &str) -> (Vec<(String, String)>, &str) {
+    let mut parameters = Vec::new();
+    while let Some(element) = text.strip_prefix('[') {
+        let id_end = element.find([' ', ']']).unwrap_or(element.len());
+        let id = &element[..id_end];
+        let mut rest = &element[id_end..];
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if let Some(after) = rest.strip_prefix(']') {
+                rest = after;
+                break;
+            }
+            let Some((name, value)) = rest.split_once("=\"") else {
+                // malformed, the rest is the message.
+                return (parameters, text);
+            };
+            let mut unescaped = String::new();
+            let mut chars = value.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((_, '\\')) => if let Some((_, c)) = chars.next() {
+                        if !matches!(c, '"' | '\\' | ']') {
+                            unescaped.push('\\');
+                        }
+                        unescaped.push(c);
+                    },
+                    Some((i, '"')) => break i,
+                    Some((_, c)) => unescaped.push(c),
+                    None => return (parameters, text),
+                }
+            };
+            parameters.push((format!("{}.{}", id, name), unescaped));
+            rest = &value[end + 1..];
+        }
+        text = rest;
+    }
+    (parameters, text)
+}
+
+// Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG, where the senders often leave out the hostname or everything before the message.
+// https://datatracker.ietf.org/doc/html/rfc3164#section-4.1
+fn parse_rfc3164(priority: u8, text: &str, now: DateTime<Local>) -> SyslogMessage {
+    let mut message = SyslogMessage {
+        priority,
+        ..Default::default()
+    };
+    let mut rest = text;
+    // the timestamp has no year, a date in the future is from last year.
+    if let Some(timestamp) = rest.get(..15)
+        .and_then(|timestamp| NaiveDateTime::parse_from_str(&format!("{} {}", now.year(), timestamp), "%Y %b %e %H:%M:%S").ok()) {
+        let timestamp = match Local.from_local_datetime(&timestamp).earliest() {
+            Some(t) if t > now + chrono::Duration::days(1) => timestamp.with_year(now.year() - 1).and_then(|t| Local.from_local_datetime(&t).earliest()),
+            t => t,
+        };
+        message.timestamp = timestamp.map(|t| t.to_utc());
+        rest = rest[15..].trim_start();
+
+        if let Some((hostname, after)) = rest.split_once(' ') {
+            if !hostname.ends_with(':') && !hostname.contains('[') {
+                message.hostname = Some(hostname.to_string());
+                rest = after;
+            }
+        }
+    }
+
+    // the tag is the program name, optionally followed by the pid, and a colon.
+    let tag_end = rest.find([':', '[', ' ']);
+    let tag = match tag_end {
+        Some(end) if end > 0 => {
+            let (app_name, after) = rest.split_at(end);
+            match after.strip_prefix('[').and_then(|after| after.split_once("]:")) {
+                Some((pid, message)) => Some((app_name, Some(pid), message)),
+                None => after.strip_prefix(':').map(|message| (app_name, None, message)),
+            }
+        },
+        _ => None,
+    };
+    if let Some((app_name, proc_id, after)) = tag {
+        message.app_name = Some(app_name.to_string());
+        message.proc_id = proc_id.map(str::to_string);
+        rest = after.strip_prefix(' ').unwrap_or(after);
+    }
+    message.message = rest.to_string();
+    message
+}
+
+fn to_otlp(message: SyslogMessage) -> ExportLogsServiceRequest {
+    let (severity_text, severity_number) = SEVERITIES[(message.priority % 8) as usize];
+    let mut resource_attributes = Vec::new();
+    if let Some(hostname) = &message.hostname {
+        resource_attributes.push(string_attribute(HOST_NAME, hostname));
+    }
+    let mut attributes = vec![string_attribute("syslog.facility", FACILITIES[(message.priority / 8) as usize])];
+    match message.proc_id.as_deref().map(|id| (id, id.parse::<i64>())) {
+        Some((_, Ok(pid))) => resource_attributes.push(int_attribute(PROCESS_PID, pid)),
+        Some((id, Err(_))) => attributes.push(string_attribute("syslog.procid", id)),
+        None => {},
+    }
+    if let Some(msg_id) = &message.msg_id {
+        attributes.push(string_attribute("syslog.msgid", msg_id));
+    }
+    attributes.extend(message.structured_data.iter().map(|(key, value)| string_attribute(key, value)));
+
+    let service_name = message.app_name.as_deref().unwrap_or(DEFAULT_SERVICE_NAME);
+    let record = LogRecord {
+        time_unix_nano: message.timestamp.and_then(|t| t.timestamp_nanos_opt()).unwrap_or_default() as u64,
+        observed_time_unix_nano: Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64,
+        severity_number,
+        severity_text: severity_text.to_string(),
+        body: Some(AnyValue { value: Some(any_value::Value::StringValue(message.message)) }),
+        attributes,
+        ..Default::default()
+    };
+    ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs {
+            resource: Some(service_resource(service_name, resource_attributes)),
+            scope_logs: vec![ScopeLogs {
+                log_records: vec![record],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::logs::{LogDto, Severity};
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap()
+    }
+
+    fn to_dto(message: SyslogMessage) -> LogDto {
+        let request = to_otlp(message);
+        let resource_logs = &request.resource_logs[0];
+        LogDto::from_otlp(resource_logs.scope_logs[0].log_records[0].clone(), None, resource_logs.resource.as_ref())
+    }
+
+    #[test]
+    fn test_parse_rfc5424() {
+        let text = r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog 8710 ID47 [exampleSDID@32473 iut="3" eventSource="App\"lication"][origin ip="10.0.0.1"] An application event"#;
+        let message = parse(text, now());
+        assert_eq!(SyslogMessage {
+            priority: 165,
+            timestamp: Some(Utc.with_ymd_and_hms(2003, 10, 11, 22, 14, 15).unwrap() + chrono::Duration::milliseconds(3)),
+            hostname: Some("mymachine.example.com".to_string()),
+            app_name: Some("evntslog".to_string()),
+            proc_id: Some("8710".to_string()),
+            msg_id: Some("ID47".to_string()),
+            structured_data: vec![
+                ("exampleSDID@32473.iut".to_string(), "3".to_string()),
+                ("exampleSDID@32473.eventSource".to_string(), "App\"lication".to_string()),
+                ("origin.ip".to_string(), "10.0.0.1".to_string()),
+            ],
+            message: "An application event".to_string(),
+        }, message);
+
+        let dto = to_dto(message);
+        assert_eq!(Severity::Info, dto.severity);
+        assert_eq!("evntslog", dto.resource.service_name);
+        assert_eq!("mymachine.example.com", dto.resource.attributes[HOST_NAME]);
+        assert_eq!("8710", dto.resource.attributes[PROCESS_PID]);
+        assert_eq!("local4", dto.tags["syslog.facility"]);
+        assert_eq!("10.0.0.1", dto.tags["origin.ip"]);
+    }
+
+    #[test]
+    fn test_parse_rfc5424_without_structured_data() {
+        let message = parse("<11>1 - - - - - - \u{feff}disk full", now());
+        assert_eq!(None, message.timestamp);
+        assert_eq!(None, message.app_name);
+        assert!(message.structured_data.is_empty());
+        assert_eq!("disk full", message.message);
+
+        let dto = to_dto(message);
+        assert_eq!(Severity::Error, dto.severity);
+        assert_eq!(DEFAULT_SERVICE_NAME, dto.resource.service_name);
+    }
+
+    #[test]
+    fn test_parse_rfc3164() {
+        let message = parse("<190>Oct  9 22:14:15 web-1 nginx[42]: GET / 200", now());
+        assert_eq!(Some(Local.with_ymd_and_hms(2026, 10, 9, 22, 14, 15).unwrap().to_utc()), message.timestamp);
+        assert_eq!(Some("web-1".to_string()), message.hostname);
+        assert_eq!(Some("nginx".to_string()), message.app_name);
+        assert_eq!(Some("42".to_string()), message.proc_id);
+        assert_eq!("GET / 200", message.message);
+
+        // without hostname, and from last year.
+        let message = parse("<28>Dec 31 23:59:59 postgres: checkpoint starting", now());
+        assert_eq!(2025, message.timestamp.unwrap().with_timezone(&Local).year());
+        assert_eq!(None, message.hostname);
+        assert_eq!(Some("postgres".to_string()), message.app_name);
+        assert_eq!(Severity::Warn, to_dto(message).severity);
+
+        let message = parse("just a line", now());
+        assert_eq!(DEFAULT_PRIORITY, message.priority);
+        assert_eq!(None, message.app_name);
+        assert_eq!("just a line", message.message);
+    }
+
+    #[tokio::test]
+    async fn test_read_frame() {
+        let mut reader = BufReader::new(&b"11 <13>1 - - -<13>newline\n<13>last"[..]);
+        assert_eq!(b"<13>1 - - -".to_vec(), read_frame(&mut reader).await.unwrap().unwrap());
+        assert_eq!(b"<13>newline\n".to_vec(), read_frame(&mut reader).await.unwrap().unwrap());
+        assert_eq!(b"<13>last".to_vec(), read_frame(&mut reader).await.unwrap().unwrap());
+        assert_eq!(None, read_frame(&mut reader).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_too_long() {
+        let unterminated = vec![b'a'; MAX_MESSAGE_SIZE + 10];
+        let error = read_frame(&mut BufReader::new(&unterminated[..])).await.unwrap_err();
+        assert_eq!(std::io::ErrorKind::InvalidData, error.kind());
+
+        let mut terminated = vec![b'a'; MAX_MESSAGE_SIZE];
+        terminated.push(b'\n');
+        assert_eq!(Some(terminated.clone()), read_frame(&mut BufReader::new(&terminated[..])).await.unwrap());
+
+        let unterminated_length = vec![b'1'; MAX_MESSAGE_SIZE];
+        assert!(read_frame(&mut BufReader::new(&unterminated_length[..])).await.is_err());
+    }
+}
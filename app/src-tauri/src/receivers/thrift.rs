@@ -0,0 +1,179 @@
+use std::fmt::{Display, Formatter};
+
+// https://github.com/apache/thrift/blob/master/doc/specs/thrift-binary-protocol.md
+pub const STOP: u8 = 0;
+pub const BOOL: u8 = 2;
+pub const BYTE: u8 = 3;
+pub const DOUBLE: u8 = 4;
+pub const I16: u8 = 6;
+pub const I32: u8 = 8;
+pub const I64: u8 = 10;
+pub const STRING: u8 = 11;
+pub const STRUCT: u8 = 12;
+pub const MAP: u8 = 13;
+pub const SET: u8 = 14;
+pub const LIST: u8 = 15;
+
+// nested structures deeper than this are rejected, so a malformed body can't exhaust the stack.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug)]
+pub struct ThriftError(String);
+
+impl Display for ThriftError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ThriftError {}
+
+/// Reads values encoded with the Thrift binary protocol.
+/// The structs are read field by field, the caller decides which fields it knows and skips the others.
+pub struct ThriftReader<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> ThriftReader<'a> {
+    pub fn new(buffer: &'a [u8]) -> Self {
+        Self { buffer }
+    }
+
+    /// Returns the type and id of the next struct field, or None at the end of the struct.
+    pub fn read_field_header(&mut self) -> Result<Option<(u8, i16)>, ThriftError> {
+        let field_type = self.read_byte()? as u8;
+        if field_type == STOP {
+            return Ok(None);
+        }
+        Ok(Some((field_type, self.read_i16()?)))
+    }
+
+    /// Returns the element type and the size of a list or set.
+    pub fn read_list_header(&mut self) -> Result<(u8, usize), ThriftError> {
+        let element_type = self.read_byte()? as u8;
+        Ok((element_type, self.read_size()?))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, ThriftError> {
+        Ok(self.read_byte()? != 0)
+    }
+
+    pub fn read_byte(&mut self) -> Result<i8, ThriftError> {
+        Ok(i8::from_be_bytes(self.take::<1>()?))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, ThriftError> {
+        Ok(i16::from_be_bytes(self.take::<2>()?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, ThriftError> {
+        Ok(i32::from_be_bytes(self.take::<4>()?))
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64, ThriftError> {
+        Ok(i64::from_be_bytes(self.take::<8>()?))
+    }
+
+    pub fn read_double(&mut self) -> Result<f64, ThriftError> {
+        Ok(f64::from_be_bytes(self.take::<8>()?))
+    }
+
+    pub fn read_binary(&mut self) -> Result<Vec<u8>, ThriftError> {
+        let size = self.read_size()?;
+        if size > self.buffer.len() {
+            return Err(ThriftError(format!("Binary of {} bytes is longer than the message.", size)));
+        }
+        let (value, rest) = self.buffer.split_at(size);
+        self.buffer = rest;
+        Ok(value.to_vec())
+    }
+
+    pub fn read_string(&mut self) -> Result<String, ThriftError> {
+        String::from_utf8(self.read_binary()?).map_err(|e| ThriftError(e.to_string()))
+    }
+
+    /// Skips a value of the given type, used for the unknown fields.
+    pub fn skip(&mut self, field_type: u8) -> Result<(), ThriftError> {
+        self.skip_nested(field_type, 0)
+    }
+
+    fn skip_nested(&mut self, field_type: u8, depth: usize) -> Result<(), ThriftError> {
+        if depth > MAX_DEPTH {
+            return Err(ThriftError("Message is nested too deep.".to_string()));
+        }
+        match field_type {
+            BOOL | BYTE => { self.read_byte()?; },
+            I16 => { self.read_i16()?; },
+            I32 => { self.read_i32()?; },
+            I64 | DOUBLE => { self.read_i64()?; },
+            STRING => { self.read_binary()?; },
+            STRUCT => {
+                while let Some((field_type, _)) = self.read_field_header()? {
+                    self.skip_nested(field_type, depth + 1)?;
+                }
+            },
+            MAP => {
+                let key_type = self.read_byte()? as u8;
+                let value_type = self.read_byte()? as u8;
+                for _ in 0..self.read_size()? {
+                    self.skip_nested(key_type, depth + 1)?;
+                    self.skip_nested(value_type, depth + 1)?;
+                }
+            },
+            SET | LIST => {
+                let (element_type, size) = self.read_list_header()?;
+                for _ in 0..size {
+                    self.skip_nested(element_type, depth + 1)?;
+                }
+            },
+            _ => return Err(ThriftError(format!("Unknown field type {}.", field_type))),
+        }
+        Ok(())
+    }
+
+    fn read_size(&mut self) -> Result<usize, ThriftError> {
+        let size = self.read_i32()?;
+        usize::try_from(size).map_err(|_| ThriftError(format!("Negative size {}.", size)))
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ThriftError> {
+        if self.buffer.len() < N {
+            return Err(ThriftError("Unexpected end of message.".to_string()));
+        }
+        let (value, rest) = self.buffer.split_at(N);
+        self.buffer = rest;
+        Ok(value.try_into().expect("Slice has the requested size."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_struct() {
+        let mut bytes = vec![STRING, 0, 1, 0, 0, 0, 2, b'h', b'i'];
+        bytes.extend([I64, 0, 2]);
+        bytes.extend(42i64.to_be_bytes());
+        // an unknown list field, which is skipped.
+        bytes.extend([LIST, 0, 3, I32, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2]);
+        bytes.push(STOP);
+
+        let mut reader = ThriftReader::new(&bytes);
+        assert_eq!(Some((STRING, 1)), reader.read_field_header().unwrap());
+        assert_eq!("hi", reader.read_string().unwrap());
+        assert_eq!(Some((I64, 2)), reader.read_field_header().unwrap());
+        assert_eq!(42, reader.read_i64().unwrap());
+        assert_eq!(Some((LIST, 3)), reader.read_field_header().unwrap());
+        reader.skip(LIST).unwrap();
+        assert_eq!(None, reader.read_field_header().unwrap());
+    }
+
+    #[test]
+    fn test_truncated_message() {
+        let mut reader = ThriftReader::new(&[STRING, 0, 1, 0, 0, 0, 10, b'h']);
+        assert!(reader.read_field_header().is_ok());
+        assert!(reader.read_string().is_err());
+        assert!(ThriftReader::new(&[0, 0, 0, 0xff]).read_binary().is_err());
+    }
+}
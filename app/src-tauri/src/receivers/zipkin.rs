@@ -0,0 +1,346 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{middleware, Router};
+use prost::Message as ProstMessage;
+use serde::Deserialize;
+use tower_http::decompression::RequestDecompressionLayer;
+use opentelemetry_semantic_conventions::attribute::{NETWORK_PEER_ADDRESS, NETWORK_PEER_PORT, PEER_SERVICE};
+use crate::app_state::AppState;
+use crate::auth::require_ingest_token;
+use crate::limits::limit_ingest;
+use crate::domain::hex_to_bytes;
+use crate::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+use crate::opentelemetry::proto::trace::v1::{span, status, ResourceSpans, ScopeSpans, Span, Status};
+use crate::receivers::{int_attribute, service_resource, string_attribute, unit_to_nanos};
+use crate::server::shutdown_signal;
+use crate::web_server::extract_request;
+
+mod proto {
+    include!(concat!(env!("OUT_DIR"), "/zipkin.proto3.rs"));
+}
+
+const ERROR_TAG: &str = "error";
+
+pub async fn init_zipkin(state: AppState, address: String) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = tokio::net::TcpListener::bind(&address).await?;
+    println!("Zipkin receiver listening on http://{}", address);
+    let app = Router::new()
+        .route("/api/v2/spans", post(handle_spans))
+        // the zipkin reporters compress the spans by default.
+        .layer(RequestDecompressionLayer::new())
+        .route_layer(middleware::from_fn_with_state(state.clone(), limit_ingest))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_ingest_token))
+        .with_state(state);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+    Ok(())
+}
+
+// Resources:
+// Api: https://zipkin.io/zipkin-api/#/default/post_spans
+// Collector-Go: https://github.com/open-telemetry/opentelemetry-collector-contrib/tree/main/pkg/translator/zipkin/zipkinv2
+async fn handle_spans(State(state): State<AppState>, request: axum::extract::Request) -> Response {
+    let r = extract_request(request,
+                            state.config.limits.max_request_size,
+                            |body| proto::ListOfSpans::decode(body).map(|list| to_otlp(list.spans)),
+                            |body| serde_json::from_slice::<Vec<JsonSpan>>(&body)
+                                .map(|spans| to_otlp(spans.into_iter().map(proto::Span::from).collect()))).await;
+    match r {
+        Ok(request) => {
+            state.request_processor.process_traces(request).await;
+            StatusCode::ACCEPTED.into_response()
+        },
+        Err(e) => e
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSpan {
+    trace_id: String,
+    parent_id: Option<String>,
+    id: String,
+    kind: Option<String>,
+    name: Option<String>,
+    timestamp: Option<u64>,
+    duration: Option<u64>,
+    local_endpoint: Option<JsonEndpoint>,
+    remote_endpoint: Option<JsonEndpoint>,
+    #[serde(default)]
+    annotations: Vec<JsonAnnotation>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonEndpoint {
+    service_name: Option<String>,
+    ipv4: Option<String>,
+    ipv6: Option<String>,
+    port: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct JsonAnnotation {
+    timestamp: u64,
+    value: String,
+}
+
+impl From<JsonSpan> for proto::Span {
+    fn from(value: JsonSpan) -> Self {
+        let kind = match value.kind.as_deref() {
+            Some("CLIENT") => proto::span::Kind::Client,
+            Some("SERVER") => proto::span::Kind::Server,
+            Some("PRODUCER") => proto::span::Kind::Producer,
+            Some("CONSUMER") => proto::span::Kind::Consumer,
+            _ => proto::span::Kind::SpanKindUnspecified,
+        };
+        proto::Span {
+            trace_id: parse_id(&value.trace_id),
+            parent_id: value.parent_id.as_deref().map(parse_id).unwrap_or_default(),
+            id: parse_id(&value.id),
+            kind: kind.into(),
+            name: value.name.unwrap_or_default(),
+            timestamp: value.timestamp.unwrap_or_default(),
+            duration: value.duration.unwrap_or_default(),
+            local_endpoint: value.local_endpoint.map(proto::Endpoint::from),
+            remote_endpoint: value.remote_endpoint.map(proto::Endpoint::from),
+            annotations: value.annotations.into_iter()
+                .map(|a| proto::Annotation { timestamp: a.timestamp, value: a.value })
+                .collect(),
+            tags: value.tags,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<JsonEndpoint> for proto::Endpoint {
+    fn from(value: JsonEndpoint) -> Self {
+        proto::Endpoint {
+            service_name: value.service_name.unwrap_or_default(),
+            ipv4: value.ipv4.and_then(|ip| ip.parse::<Ipv4Addr>().ok()).map(|ip| ip.octets().to_vec()).unwrap_or_default(),
+            ipv6: value.ipv6.and_then(|ip| ip.parse::<Ipv6Addr>().ok()).map(|ip| ip.octets().to_vec()).unwrap_or_default(),
+            port: value.port.unwrap_or_default(),
+        }
+    }
+}
+
+// zipkin ids are 64 or 128 bit and may come without the leading zeros, invalid ids are left empty.
+fn parse_id(hex: &str) -> Vec<u8> {
+    let width = if hex.len() > 16 { 32 } else { 16 };
+    hex_to_bytes(&format!("{:0>width$}", hex, width = width)).unwrap_or_default()
+}
+
+/// Groups the spans by the service of their local endpoint, which becomes the resource.
+fn to_otlp(spans: Vec<proto::Span>) -> ExportTraceServiceRequest {
+    let mut services: BTreeMap<String, Vec<Span>> = BTreeMap::new();
+    for zipkin_span in spans {
+        let service_name = zipkin_span.local_endpoint.as_ref()
+            .map(|e| e.service_name.clone())
+            .unwrap_or_default();
+        services.entry(service_name).or_default().push(to_otlp_span(zipkin_span));
+    }
+    ExportTraceServiceRequest {
+        resource_spans: services.into_iter().map(|(service_name, spans)| ResourceSpans {
+            resource: Some(service_resource(&service_name, Vec::new())),
+            scope_spans: vec![ScopeSpans { spans, ..Default::default() }],
+            ..Default::default()
+        }).collect(),
+    }
+}
+
+fn to_otlp_span(zipkin_span: proto::Span) -> Span {
+    let kind = match zipkin_span.kind() {
+        proto::span::Kind::Client => span::SpanKind::Client,
+        proto::span::Kind::Server => span::SpanKind::Server,
+        proto::span::Kind::Producer => span::SpanKind::Producer,
+        proto::span::Kind::Consumer => span::SpanKind::Consumer,
+        // a span without kind is a local one.
+        proto::span::Kind::SpanKindUnspecified => span::SpanKind::Internal,
+    };
+    let start = micros_to_nanos(zipkin_span.timestamp);
+    let end = micros_to_nanos(zipkin_span.timestamp.saturating_add(zipkin_span.duration));
+
+    let mut tags = zipkin_span.tags;
+    // zipkin marks the failed spans with the error tag, its value is the message.
+    let status = tags.remove(ERROR_TAG).map(|message| Status {
+        message,
+        code: status::StatusCode::Error.into(),
+    });
+    let mut attributes = tags.into_iter()
+        .map(|(key, value)| string_attribute(&key, value))
+        .collect::<Vec<_>>();
+    if let Some(remote) = zipkin_span.remote_endpoint {
+        if !remote.service_name.is_empty() {
+            attributes.push(string_attribute(PEER_SERVICE, remote.service_name));
+        }
+        if let Some(address) = endpoint_address(&remote.ipv4, &remote.ipv6) {
+            attributes.push(string_attribute(NETWORK_PEER_ADDRESS, address));
+        }
+        if remote.port != 0 {
+            attributes.push(int_attribute(NETWORK_PEER_PORT, remote.port as i64));
+        }
+    }
+
+    let events = zipkin_span.annotations.into_iter().map(|annotation| span::Event {
+        time_unix_nano: micros_to_nanos(annotation.timestamp),
+        name: annotation.value,
+        ..Default::default()
+    }).collect();
+
+    Span {
+        trace_id: to_trace_id(zipkin_span.trace_id),
+        span_id: zipkin_span.id,
+        parent_span_id: zipkin_span.parent_id,
+        name: zipkin_span.name,
+        kind: kind.into(),
+        start_time_unix_nano: start,
+        end_time_unix_nano: end,
+        attributes,
+        events,
+        status,
+        ..Default::default()
+    }
+}
+
+// otlp trace ids are always 128 bit, the 64 bit ones get zeros in front.
+// zipkin counts in microseconds, unsigned.
+fn micros_to_nanos(micros: u64) -> u64 {
+    i64::try_from(micros).map_or(0, |micros| unit_to_nanos(micros, 1000))
+}
+
+fn to_trace_id(id: Vec<u8>) -> Vec<u8> {
+    if id.len() == 8 {
+        [vec![0; 8], id].concat()
+    } else {
+        id
+    }
+}
+
+fn endpoint_address(ipv4: &[u8], ipv6: &[u8]) -> Option<String> {
+    if let Ok(ip) = <[u8; 4]>::try_from(ipv4) {
+        Some(Ipv4Addr::from(ip).to_string())
+    } else if let Ok(ip) = <[u8; 16]>::try_from(ipv6) {
+        Some(Ipv6Addr::from(ip).to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::traces::{SpanDto, SpanKind, SpanStatusCode};
+
+    const JSON_SPANS: &str = r#"[{
+        "traceId": "5982fe77008310cc80f1da5e10147517",
+        "parentId": "90394f6bcffb5d13",
+        "id": "67fae42571535f60",
+        "kind": "SERVER",
+        "name": "get /api",
+        "timestamp": 1472470996199000,
+        "duration": 207000,
+        "localEndpoint": { "serviceName": "frontend", "ipv4": "127.0.0.1" },
+        "remoteEndpoint": { "serviceName": "backend", "ipv4": "192.168.99.101", "port": 9000 },
+        "annotations": [{ "timestamp": 1472470996238000, "value": "ws" }],
+        "tags": { "http.path": "/api", "error": "boom" }
+    }, {
+        "traceId": "80f1da5e10147517",
+        "id": "1",
+        "name": "local",
+        "timestamp": 1472470996199000,
+        "localEndpoint": { "serviceName": "backend" }
+    }]"#;
+
+    fn convert(json: &str) -> ExportTraceServiceRequest {
+        let spans = serde_json::from_str::<Vec<JsonSpan>>(json).unwrap();
+        to_otlp(spans.into_iter().map(proto::Span::from).collect())
+    }
+
+    fn to_dtos(request: ExportTraceServiceRequest) -> Vec<SpanDto> {
+        request.resource_spans.into_iter().flat_map(|resource_spans| {
+            let resource = resource_spans.resource;
+            resource_spans.scope_spans.into_iter()
+                .flat_map(|scope_spans| scope_spans.spans)
+                .map(|span| SpanDto::from_otlp(span, None, resource.as_ref()))
+                .collect::<Vec<_>>()
+        }).collect()
+    }
+
+    #[test]
+    fn test_json_spans() {
+        let dtos = to_dtos(convert(JSON_SPANS));
+        assert_eq!(2, dtos.len());
+
+        // the resources are sorted by service name.
+        let local = &dtos[0];
+        assert_eq!("backend", local.resource.service_name);
+        assert_eq!("\"000000000000000080f1da5e10147517\"", serde_json::to_string(&local.trace_id).unwrap());
+        assert!(local.parent_span_id.is_none());
+        assert!(matches!(local.kind, SpanKind::Internal));
+
+        let server = &dtos[1];
+        assert_eq!("frontend", server.resource.service_name);
+        assert_eq!("get /api", server.name);
+        assert!(matches!(server.kind, SpanKind::Server));
+        assert_eq!(1472470996199000000, server.start_time.timestamp_nanos_opt().unwrap());
+        assert_eq!(1472470996406000000, server.end_time.timestamp_nanos_opt().unwrap());
+        assert!(matches!(server.status.code, SpanStatusCode::Error));
+        assert_eq!("boom", server.status.message);
+        assert_eq!(1, server.events.len());
+        assert_eq!("ws", server.events[0].name);
+        assert_eq!(Some(&"/api".to_string()), server.tags.get("http.path"));
+        assert_eq!(Some(&"backend".to_string()), server.tags.get(PEER_SERVICE));
+        assert_eq!(Some(&"192.168.99.101".to_string()), server.tags.get(NETWORK_PEER_ADDRESS));
+        assert_eq!(Some(&"9000".to_string()), server.tags.get(NETWORK_PEER_PORT));
+        assert!(!server.tags.contains_key(ERROR_TAG));
+    }
+
+    #[test]
+    fn test_proto_spans() {
+        let spans = proto::ListOfSpans {
+            spans: vec![proto::Span {
+                trace_id: vec![1; 16],
+                id: vec![2; 8],
+                kind: proto::span::Kind::Client.into(),
+                name: "call".to_string(),
+                timestamp: 10,
+                duration: 5,
+                local_endpoint: Some(proto::Endpoint { service_name: "checkout".to_string(), ..Default::default() }),
+                ..Default::default()
+            }],
+        };
+        let decoded = proto::ListOfSpans::decode(spans.encode_to_vec().as_slice()).unwrap();
+        let dtos = to_dtos(to_otlp(decoded.spans));
+        assert_eq!(1, dtos.len());
+        assert_eq!("checkout", dtos[0].resource.service_name);
+        assert!(matches!(dtos[0].kind, SpanKind::Client));
+        assert!(matches!(dtos[0].status.code, SpanStatusCode::Unset));
+        assert_eq!(5000, dtos[0].end_time.timestamp_nanos_opt().unwrap() - dtos[0].start_time.timestamp_nanos_opt().unwrap());
+    }
+
+    #[test]
+    fn test_out_of_range_times() {
+        let span = to_otlp_span(proto::Span {
+            timestamp: u64::MAX,
+            duration: 5,
+            annotations: vec![proto::Annotation { timestamp: u64::MAX, value: "ws".to_string() }],
+            ..Default::default()
+        });
+        assert_eq!((0, 0), (span.start_time_unix_nano, span.end_time_unix_nano));
+        assert_eq!(0, span.events[0].time_unix_nano);
+    }
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 1], parse_id("1"));
+        assert_eq!(16, parse_id("80f1da5e10147517a").len());
+        assert!(parse_id("not hex").is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use uuid::Uuid;
+use crate::auth::percent_encode;
+use crate::config::Config;
+use crate::web_server::HTTP_PORT;
+
+// any address outside of the machine, a documentation one, nothing is sent to it.
+const PROBE_ADDRESS: &str = "192.0.2.1:9";
+
+/// The settings of the desktop app: when it serves the UI to other browsers, the UI has a token, a generated one by default.
+pub fn desktop_config() -> Config {
+    let mut config = Config::from_env();
+    ensure_ui_token(&mut config);
+    config
+}
+
+fn ensure_ui_token(config: &mut Config) {
+    if config.serve_ui && config.auth.ui_token.is_none() {
+        config.auth.ui_token = Some(Uuid::new_v4().simple().to_string());
+    }
+}
+
+/// The url which opens the UI from another device, logged in with the UI token.
+pub fn ui_url(config: &Config) -> String {
+    let host = network_address().unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    url(config, host)
+}
+
+fn url(config: &Config, host: IpAddr) -> String {
+    let scheme = if config.tls.is_some() { "https" } else { "http" };
+    let mut url = format!("{}://{}/", scheme, SocketAddr::new(host, HTTP_PORT));
+    if let Some(ui_token) = &config.auth.ui_token {
+        url.push_str("?token=");
+        url.push_str(&percent_encode(ui_token));
+    }
+    url
+}
+
+// the address of the interface of the default route, connecting a udp socket only picks it.
+fn network_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect(PROBE_ADDRESS).ok()?;
+    socket.local_addr().ok().map(|address| address.ip()).filter(|ip| !ip.is_unspecified())
+}
+
+/// Runs in the desktop webview before the UI, which isn't served by the http port and has no cookie of the UI token.
+/// The UI connects with the token and shows the url for the other devices.
+pub fn init_script(config: &Config) -> String {
+    let settings = serde_json::json!({
+        "uiToken": config.auth.ui_token,
+        "uiUrl": config.serve_ui.then(|| ui_url(config)),
+    });
+    format!("window.__INSPECTOR__ = {};", settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_ui_token() {
+        let mut config = Config { serve_ui: false, ..Config::default() };
+        ensure_ui_token(&mut config);
+        assert_eq!(None, config.auth.ui_token);
+
+        config.serve_ui = true;
+        ensure_ui_token(&mut config);
+        assert_eq!(Some(32), config.auth.ui_token.as_ref().map(String::len));
+
+        config.auth.ui_token = Some("mine".to_string());
+        ensure_ui_token(&mut config);
+        assert_eq!(Some("mine".to_string()), config.auth.ui_token);
+    }
+
+    #[test]
+    fn test_url() {
+        let mut config = Config::default();
+        assert_eq!("http://192.168.1.5:4318/", url(&config, "192.168.1.5".parse().unwrap()));
+
+        config.auth.ui_token = Some("a b/c".to_string());
+        assert_eq!("http://[fd00::5]:4318/?token=a%20b%2Fc", url(&config, "fd00::5".parse().unwrap()));
+    }
+}
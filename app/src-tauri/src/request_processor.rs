@@ -3,56 +3,90 @@ use tokio::sync::RwLock;
 use crate::domain::logs::LogDto;
 use crate::domain::metrics::MetricDto;
 use crate::domain::traces::SpanDto;
+use crate::forwarder::{ExportRequest, Forwarder};
+use crate::metrics_exposition::MetricsExposition;
 use crate::opentelemetry::proto::collector::logs::v1::ExportLogsServiceRequest;
 use crate::opentelemetry::proto::collector::metrics::v1::ExportMetricsServiceRequest;
 use crate::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
-use crate::subscription_manager::SubscriptionManager;
+use crate::subscription_manager::{SubscriptionManager, TopicMessage};
+use crate::telemetry_store::TelemetryStore;
 
 pub struct RequestProcessor {
-    subscription_manager: Arc<RwLock<SubscriptionManager>>
+    subscription_manager: Arc<RwLock<SubscriptionManager>>,
+    telemetry_store: Arc<RwLock<TelemetryStore>>,
+    metrics_exposition: Arc<RwLock<MetricsExposition>>,
+    forwarder: Option<Forwarder>,
 }
 
 impl RequestProcessor {
-    pub fn new(subscription_manager: Arc<RwLock<SubscriptionManager>>) -> Self {
-        Self { subscription_manager }
+    pub fn new(subscription_manager: Arc<RwLock<SubscriptionManager>>,
+               telemetry_store: Arc<RwLock<TelemetryStore>>,
+               metrics_exposition: Arc<RwLock<MetricsExposition>>,
+               forwarder: Option<Forwarder>) -> Self {
+        Self { subscription_manager, telemetry_store, metrics_exposition, forwarder }
     }
 
     pub async fn process_logs(&self, request: ExportLogsServiceRequest) {
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.forward(ExportRequest::Logs(request.clone()));
+        }
+        let mut messages = Vec::new();
         for resource_log in request.resource_logs {
             let resource = resource_log.resource.as_ref();
             for scope_log in resource_log.scope_logs {
                 let scope = scope_log.scope.as_ref();
                 for log_record in scope_log.log_records {
                     let dto = LogDto::from_otlp(log_record, scope, resource);
-                    let _ = self.subscription_manager.read().await.publish_log(dto);
+                    messages.push(TopicMessage::from(dto));
                 }
             }
         }
+        self.publish(messages).await;
     }
 
     pub async fn process_traces(&self, request: ExportTraceServiceRequest) {
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.forward(ExportRequest::Traces(request.clone()));
+        }
+        let mut messages = Vec::new();
         for resource_span in request.resource_spans {
             let resource = resource_span.resource.as_ref();
             for scope_span in resource_span.scope_spans {
                 let scope = scope_span.scope.as_ref();
                 for span in scope_span.spans {
                     let dto = SpanDto::from_otlp(span, scope, resource);
-                    let _ = self.subscription_manager.read().await.publish_span(dto);
+                    messages.push(TopicMessage::from(dto));
                 }
             }
         }
+        self.publish(messages).await;
     }
 
     pub async fn process_metrics(&self, request: ExportMetricsServiceRequest) {
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.forward(ExportRequest::Metrics(request.clone()));
+        }
+        self.metrics_exposition.write().await.update(&request);
+        let mut messages = Vec::new();
         for resource_span in request.resource_metrics {
             let resource = resource_span.resource.as_ref();
             for scope_metrics in resource_span.scope_metrics {
                 let scope = scope_metrics.scope.as_ref();
                 for metric in scope_metrics.metrics {
                     let dto = MetricDto::from_otlp(metric, scope, resource);
-                    let _ = self.subscription_manager.read().await.publish_metric(dto);
+                    messages.push(TopicMessage::from(dto));
                 }
             }
         }
+        self.publish(messages).await;
     }
-}
\ No newline at end of file
+
+    // store the messages first, so they get their sequence number, then send them to the subscribers.
+    async fn publish(&self, messages: Vec<TopicMessage>) {
+        let messages = self.telemetry_store.write().await.append(messages);
+        let subscription_manager = self.subscription_manager.read().await;
+        for message in messages {
+            let _ = subscription_manager.publish_message(message);
+        }
+    }
+}
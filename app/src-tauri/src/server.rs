@@ -1,3 +1,13 @@
+/// Binds a unix domain socket, in place of the one a previous run left behind.
+#[cfg(unix)]
+pub fn bind_unix_socket(path: &str) -> std::io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+    if std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    tokio::net::UnixListener::bind(path)
+}
+
 pub async fn shutdown_signal() {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
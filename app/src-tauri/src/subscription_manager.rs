@@ -1,11 +1,12 @@
 #[allow(unused)]
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde::Serialize;
 use tokio::sync::broadcast;
 use tokio::sync::broadcast::Receiver;
-use crate::domain::logs::LogDto;
+use crate::domain::logs::{LogDto, Severity};
 use crate::domain::metrics::MetricDto;
 use crate::domain::traces::SpanDto;
+use crate::telemetry_store::Sequence;
 
 pub type ClientId = String;
 pub type Topic = String;
@@ -13,17 +14,17 @@ pub type Topic = String;
 #[derive(Clone, Serialize, Debug)]
 #[serde(untagged)] // no need to deserialize so we can use untagged
 pub(crate) enum TopicMessage {
-    Logs { topic: String, payload: Box<LogDto> },
-    Spans { topic: String, payload: Box<SpanDto> },
-    Metrics { topic: String, payload: Box<MetricDto> },
+    Logs { topic: String, seq: Sequence, payload: Box<LogDto> },
+    Spans { topic: String, seq: Sequence, payload: Box<SpanDto> },
+    Metrics { topic: String, seq: Sequence, payload: Box<MetricDto> },
     #[allow(dead_code)] // use any for testing purposes
-    Any { topic: String, payload: String }
+    Any { topic: String, seq: Sequence, payload: String }
 }
 
 impl TopicMessage {
     #[allow(dead_code)]
     pub fn new(topic: &str, payload: &str) -> Self {
-        TopicMessage::Any { topic: topic.to_string(), payload: payload.to_string() }
+        TopicMessage::Any { topic: topic.to_string(), seq: 0, payload: payload.to_string() }
     }
 
     pub fn topic(&self) -> &str {
@@ -34,26 +35,87 @@ impl TopicMessage {
             TopicMessage::Any { topic, .. } => topic.as_str(),
         }
     }
+
+    /// Sequence number assigned by the telemetry store, 0 when the message was not stored.
+    pub fn seq(&self) -> Sequence {
+        match self {
+            TopicMessage::Logs { seq, .. } => *seq,
+            TopicMessage::Spans { seq, .. } => *seq,
+            TopicMessage::Metrics { seq, .. } => *seq,
+            TopicMessage::Any { seq, .. } => *seq,
+        }
+    }
+
+    pub fn with_seq(mut self, value: Sequence) -> Self {
+        match &mut self {
+            TopicMessage::Logs { seq, .. } => *seq = value,
+            TopicMessage::Spans { seq, .. } => *seq = value,
+            TopicMessage::Metrics { seq, .. } => *seq = value,
+            TopicMessage::Any { seq, .. } => *seq = value,
+        }
+        self
+    }
 }
 
 impl From<LogDto> for TopicMessage {
     fn from(value: LogDto) -> Self {
-        TopicMessage::Logs { topic: "logs".to_string(), payload: Box::new(value) }
+        let severity = match &value.severity {
+            Severity::Trace => "trace",
+            Severity::Debug => "debug",
+            Severity::Info => "info",
+            Severity::Warn => "warn",
+            Severity::Error => "error",
+            Severity::Fatal => "fatal",
+            Severity::Unknown(_) => "unknown",
+        };
+        let topic = topic_path(&["logs", &value.resource.service_name, severity]);
+        TopicMessage::Logs { topic, seq: 0, payload: Box::new(value) }
     }
 }
 
 impl From<SpanDto> for TopicMessage {
     fn from(value: SpanDto) -> Self {
-        TopicMessage::Spans { topic: "traces".to_string(), payload: Box::new(value) }
+        let topic = topic_path(&["traces", &value.resource.service_name]);
+        TopicMessage::Spans { topic, seq: 0, payload: Box::new(value) }
     }
 }
 
 impl From<MetricDto> for TopicMessage {
     fn from(value: MetricDto) -> Self {
-        TopicMessage::Metrics { topic: "metrics".to_string(), payload: Box::new(value) }
+        let topic = topic_path(&["metrics", &value.resource.service_name, &value.name]);
+        TopicMessage::Metrics { topic, seq: 0, payload: Box::new(value) }
     }
 }
 
+const TOPIC_SEPARATOR: char = '/';
+const TOPIC_WILDCARD: &str = "*";
+
+// builds `logs/<service>/<severity>` like topics, the separator is not allowed inside a segment.
+fn topic_path(segments: &[&str]) -> Topic {
+    segments.iter()
+        .map(|segment| if segment.is_empty() { "unknown".to_string() } else { segment.replace(TOPIC_SEPARATOR, "_") })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// The first segment of the topic: `logs`, `traces` or `metrics`.
+pub fn topic_root(topic: &str) -> &str {
+    topic.split(TOPIC_SEPARATOR).next().unwrap_or_default()
+}
+
+/// Checks if the topic is covered by the subscription pattern.
+/// `*` matches any single segment and a pattern matches all topics below it,
+/// so `logs` matches every log and `logs/checkout/*` matches every log of the checkout service.
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    let mut topic_segments = topic.split(TOPIC_SEPARATOR);
+    pattern.split(TOPIC_SEPARATOR).all(|pattern_segment| {
+        match topic_segments.next() {
+            Some(topic_segment) => pattern_segment == TOPIC_WILDCARD || pattern_segment == topic_segment,
+            None => false,
+        }
+    })
+}
+
 #[derive(Clone)]
 pub struct SubscriptionManager {
     subscribers: HashMap<Topic, Vec<ClientId>>,
@@ -100,46 +162,33 @@ impl SubscriptionManager {
 
     #[allow(dead_code)]
     pub fn publish(&self, topic: &str, payload: &str) -> Result<usize, broadcast::error::SendError<TopicMessage>> {
-        let event = TopicMessage::new(topic, payload);
-        if let Some(tx) = self.channels.get(event.topic()) {
-            tx.send(event)
-        } else {
-            Ok(0)
-        }
+        self.publish_message(TopicMessage::new(topic, payload))
     }
 
-    pub fn publish_log(&self, payload: LogDto) -> Result<usize, broadcast::error::SendError<TopicMessage>> {
-        let event = TopicMessage::from(payload);
-        if let Some(tx) = self.channels.get(event.topic()) {
-            tx.send(event)
-        } else {
-            Ok(0)
+    /// Sends the message to every subscription which pattern matches its topic.
+    pub fn publish_message(&self, event: TopicMessage) -> Result<usize, broadcast::error::SendError<TopicMessage>> {
+        let mut result = Ok(0);
+        let mut received = 0;
+        for (pattern, tx) in &self.channels {
+            if topic_matches(pattern, event.topic()) {
+                match tx.send(event.clone()) {
+                    Ok(count) => received += count,
+                    Err(e) => result = Err(e),
+                }
+            }
         }
+        // report the error only when nobody got the message.
+        if received > 0 { Ok(received) } else { result }
     }
 
-    pub fn publish_span(&self, payload: SpanDto) -> Result<usize, broadcast::error::SendError<TopicMessage>> {
-        let event = TopicMessage::from(payload);
-        if let Some(tx) = self.channels.get(event.topic()) {
-            tx.send(event)
-        } else {
-            Ok(0)
-        }
-    }
-
-    pub fn publish_metric(&self, payload: MetricDto) -> Result<usize, broadcast::error::SendError<TopicMessage>> {
-        let event = TopicMessage::from(payload);
-        if let Some(tx) = self.channels.get(event.topic()) {
-            tx.send(event)
-        } else {
-            Ok(0)
-        }
+    pub fn client_count(&self) -> usize {
+        self.subscribers.values().flatten().collect::<HashSet<&ClientId>>().len()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use chrono::{TimeZone, Utc};
-    use crate::domain::logs::Severity;
     use crate::domain::resource::ResourceInfo;
     use super::*;
 
@@ -250,7 +299,8 @@ mod tests {
         let json = serde_json::to_string_pretty(&message).unwrap();
         //lang=JSON
         assert_eq!(r#"{
-  "topic": "logs",
+  "topic": "logs/test service/error",
+  "seq": 0,
   "payload": {
     "timestamp": "2025-01-12T14:23:20Z",
     "time_unix_nano": "1641996200000000000",
@@ -269,6 +319,52 @@ mod tests {
 }"#, json);
     }
 
+    #[test]
+    fn test_topic_matches() {
+        assert!(topic_matches("logs", "logs/checkout/error"));
+        assert!(topic_matches("logs/checkout", "logs/checkout/error"));
+        assert!(topic_matches("logs/checkout/*", "logs/checkout/error"));
+        assert!(topic_matches("logs/*/error", "logs/checkout/error"));
+        assert!(topic_matches("*", "traces/checkout"));
+        assert!(topic_matches("metrics/checkout/http.server.duration", "metrics/checkout/http.server.duration"));
+
+        assert!(!topic_matches("logs/checkout/*", "logs/cart/error"));
+        assert!(!topic_matches("logs/*/error", "logs/checkout/info"));
+        assert!(!topic_matches("logs/checkout/error/*", "logs/checkout/error"));
+        assert!(!topic_matches("log", "logs/checkout/error"));
+        assert!(!topic_matches("traces", "logs/checkout/error"));
+    }
+
+    #[test]
+    fn test_topic_path() {
+        assert_eq!("metrics/checkout/unknown", topic_path(&["metrics", "checkout", ""]));
+        assert_eq!("traces/api_v1", topic_path(&["traces", "api/v1"]));
+        assert_eq!("logs", topic_root("logs/checkout/error"));
+        assert_eq!("traces", topic_root("traces"));
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_subscription() {
+        let mut manager = SubscriptionManager::new();
+        let r0 = manager.subscribe("logs/checkout/*".to_string(), "test-client".to_string());
+        let r1 = manager.subscribe("logs".to_string(), "test-client-2".to_string());
+
+        let w0 = collect_messages(r0);
+        let w1 = collect_messages(r1);
+
+        assert_eq!(2, manager.publish("logs/checkout/error", "first").unwrap());
+        assert_eq!(1, manager.publish("logs/cart/error", "second").unwrap());
+        assert_eq!(0, manager.publish("traces/checkout", "third").unwrap());
+        assert_eq!(2, manager.client_count());
+
+        manager.unsubscribe_client(&"test-client".to_string());
+        manager.unsubscribe_client(&"test-client-2".to_string());
+        let (m0, m1) = tokio::join!(w0, w1);
+
+        assert_eq!(1, m0.unwrap().len());
+        assert_eq!(2, m1.unwrap().len());
+    }
+
     fn collect_messages(mut receiver: Receiver<TopicMessage>) -> tokio::task::JoinHandle<Vec<TopicMessage>> {
         let handle = tokio::spawn(async move {
             let mut messages: Vec<TopicMessage> = Vec::new();
@@ -0,0 +1,180 @@
+use std::collections::{HashMap, VecDeque};
+use serde::Serialize;
+use crate::subscription_manager::{topic_matches, topic_root, TopicMessage};
+
+pub type Sequence = u64;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TopicCounters {
+    pub logs: u64,
+    pub traces: u64,
+    pub metrics: u64,
+}
+
+impl TopicCounters {
+    fn increment(&mut self, topic: &str, value: u64) {
+        match topic_root(topic) {
+            "logs" => self.logs += value,
+            "traces" => self.traces += value,
+            "metrics" => self.metrics += value,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct StoreStats {
+    /// Number of messages received since the start.
+    pub received: TopicCounters,
+    /// Number of messages currently kept in the history.
+    pub stored: TopicCounters,
+}
+
+/// Keeps the last received messages per root topic (logs, traces, metrics), so clients can query what they missed.
+/// Every message gets a sequence number which grows across all topics.
+pub struct TelemetryStore {
+    capacity: usize,
+    last_sequence: Sequence,
+    messages: HashMap<String, VecDeque<TopicMessage>>,
+    received: TopicCounters,
+}
+
+impl TelemetryStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            last_sequence: 0,
+            messages: HashMap::new(),
+            received: TopicCounters::default(),
+        }
+    }
+
+    /// Stores the messages and returns them with their sequence number assigned.
+    pub fn append(&mut self, messages: Vec<TopicMessage>) -> Vec<TopicMessage> {
+        messages.into_iter().map(|message| {
+            self.last_sequence += 1;
+            let message = message.with_seq(self.last_sequence);
+            self.received.increment(message.topic(), 1);
+            if self.capacity > 0 {
+                let queue = self.messages.entry(topic_root(message.topic()).to_string()).or_default();
+                if queue.len() == self.capacity {
+                    queue.pop_front();
+                }
+                queue.push_back(message.clone());
+            }
+            message
+        }).collect()
+    }
+
+    /// Returns up to `limit` messages matching the topic pattern, which came after the `after` sequence, oldest first.
+    pub fn query(&self, pattern: &str, after: Option<Sequence>, limit: usize) -> Vec<TopicMessage> {
+        let after = after.unwrap_or_default();
+        let mut result = Vec::new();
+        for (root, queue) in &self.messages {
+            // the pattern may start with a wildcard, so check every root topic.
+            if !topic_matches(topic_root(pattern), root) {
+                continue;
+            }
+            let start = queue.partition_point(|message| message.seq() <= after);
+            result.extend(queue.range(start..)
+                .filter(|message| topic_matches(pattern, message.topic()))
+                .take(limit)
+                .cloned());
+        }
+        result.sort_by_key(TopicMessage::seq);
+        result.truncate(limit);
+        result
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+
+    pub fn stats(&self) -> StoreStats {
+        let mut stored = TopicCounters::default();
+        for (topic, queue) in &self.messages {
+            stored.increment(topic, queue.len() as u64);
+        }
+        StoreStats {
+            received: self.received.clone(),
+            stored,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(topic: &str, count: usize) -> Vec<TopicMessage> {
+        (0..count).map(|i| TopicMessage::new(topic, &i.to_string())).collect()
+    }
+
+    #[test]
+    fn test_append_assigns_sequence() {
+        let mut store = TelemetryStore::new(10);
+        let first = store.append(messages("logs", 2));
+        let second = store.append(messages("traces", 1));
+
+        assert_eq!(vec![1, 2], first.iter().map(TopicMessage::seq).collect::<Vec<_>>());
+        assert_eq!(vec![3], second.iter().map(TopicMessage::seq).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_capacity_drops_oldest() {
+        let mut store = TelemetryStore::new(3);
+        store.append(messages("logs", 5));
+
+        let stored = store.query("logs", None, 10);
+        assert_eq!(vec![3, 4, 5], stored.iter().map(TopicMessage::seq).collect::<Vec<_>>());
+
+        let stats = store.stats();
+        assert_eq!(5, stats.received.logs);
+        assert_eq!(3, stats.stored.logs);
+    }
+
+    #[test]
+    fn test_query_after_and_limit() {
+        let mut store = TelemetryStore::new(10);
+        store.append(messages("logs", 5));
+        store.append(messages("traces", 5));
+
+        let stored = store.query("logs", Some(2), 2);
+        assert_eq!(vec![3, 4], stored.iter().map(TopicMessage::seq).collect::<Vec<_>>());
+        assert!(store.query("metrics", None, 10).is_empty());
+    }
+
+    #[test]
+    fn test_query_pattern() {
+        let mut store = TelemetryStore::new(10);
+        store.append(vec![
+            TopicMessage::new("logs/checkout/error", "1"),
+            TopicMessage::new("traces/checkout", "2"),
+            TopicMessage::new("logs/cart/info", "3"),
+            TopicMessage::new("logs/checkout/info", "4"),
+        ]);
+
+        let stored = store.query("logs/checkout/*", None, 10);
+        assert_eq!(vec![1, 4], stored.iter().map(TopicMessage::seq).collect::<Vec<_>>());
+        let stored = store.query("*/checkout", None, 10);
+        assert_eq!(vec![1, 2, 4], stored.iter().map(TopicMessage::seq).collect::<Vec<_>>());
+        let stored = store.query("*", Some(1), 2);
+        assert_eq!(vec![2, 3], stored.iter().map(TopicMessage::seq).collect::<Vec<_>>());
+        assert_eq!(3, store.stats().stored.logs);
+    }
+
+    #[test]
+    fn test_clear_keeps_received_counters() {
+        let mut store = TelemetryStore::new(10);
+        store.append(messages("metrics", 2));
+        store.clear();
+
+        assert!(store.query("metrics", None, 10).is_empty());
+        let stats = store.stats();
+        assert_eq!(2, stats.received.metrics);
+        assert_eq!(0, stats.stored.metrics);
+
+        let next = store.append(messages("metrics", 1));
+        assert_eq!(3, next[0].seq());
+    }
+}
@@ -0,0 +1,121 @@
+use std::error::Error;
+use std::sync::{Arc, OnceLock};
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use axum::serve::Listener;
+use tokio_rustls::TlsAcceptor;
+use crate::config::{TlsCertificate, TlsConfig};
+use crate::server::shutdown_signal;
+
+const SELF_SIGNED_NAMES: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+
+// the grpc and http listeners share the generated certificate, so a client trusts both once it trusts one.
+static SELF_SIGNED: OnceLock<(String, String)> = OnceLock::new();
+
+/// The PEM certificate chain, private key and client CA certificates of a TLS listener.
+pub struct TlsIdentity {
+    pub cert_pem: String,
+    pub key_pem: String,
+    pub client_ca_pem: Option<String>,
+}
+
+pub fn load_identity(config: &TlsConfig) -> Result<TlsIdentity, Box<dyn Error>> {
+    let (cert_pem, key_pem) = match &config.certificate {
+        TlsCertificate::Files { cert, key } => (std::fs::read_to_string(cert)?, std::fs::read_to_string(key)?),
+        TlsCertificate::SelfSigned => self_signed()?,
+    };
+    let client_ca_pem = config.client_ca.as_ref().map(std::fs::read_to_string).transpose()?;
+    Ok(TlsIdentity { cert_pem, key_pem, client_ca_pem })
+}
+
+fn self_signed() -> Result<(String, String), Box<dyn Error>> {
+    if let Some(self_signed) = SELF_SIGNED.get() {
+        return Ok(self_signed.clone());
+    }
+    let mut names = SELF_SIGNED_NAMES.map(str::to_string).to_vec();
+    // docker sets the container host name, which the other containers of its network resolve.
+    names.extend(std::env::var("HOSTNAME").ok().filter(|name| !name.is_empty()));
+    let certified_key = rcgen::generate_simple_self_signed(names)?;
+    let generated = (certified_key.cert.pem(), certified_key.signing_key.serialize_pem());
+    if SELF_SIGNED.set(generated).is_ok() {
+        println!("Generated a self-signed TLS certificate, clients need to trust it:\n{}", SELF_SIGNED.get().map(|(cert, _)| cert.as_str()).unwrap_or_default());
+    }
+    Ok(SELF_SIGNED.get().cloned().unwrap_or_default())
+}
+
+/// The rustls configuration of the http listener, which negotiates http/2 or http/1.1.
+pub fn server_config(identity: &TlsIdentity) -> Result<ServerConfig, Box<dyn Error>> {
+    let certificates = CertificateDer::pem_slice_iter(identity.cert_pem.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_slice(identity.key_pem.as_bytes())?;
+    let builder = match &identity.client_ca_pem {
+        Some(client_ca_pem) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in CertificateDer::pem_slice_iter(client_ca_pem.as_bytes()) {
+                roots.add(certificate?)?;
+            }
+            ServerConfig::builder().with_client_cert_verifier(WebPkiClientVerifier::builder(Arc::new(roots)).build()?)
+        },
+        None => ServerConfig::builder().with_no_client_auth(),
+    };
+    let mut config = builder.with_single_cert(certificates, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Serves the router over TLS until the shutdown signal, the equivalent of `axum::serve` which only does plain text.
+pub async fn serve_tls<L>(mut listener: L, app: Router, config: ServerConfig) -> std::io::Result<()>
+    where L: Listener,
+          L::Addr: std::fmt::Debug {
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        let (stream, address) = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => return Ok(()),
+        };
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("TLS handshake with {:?} failed: {}", address, e);
+                    return;
+                },
+            };
+            // the upgrades are the websocket connections of the UI.
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await {
+                println!("Connection with {:?} failed: {}", address, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_signed_server_config() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let identity = load_identity(&TlsConfig { certificate: TlsCertificate::SelfSigned, client_ca: None }).unwrap();
+        assert!(identity.cert_pem.starts_with("-----BEGIN CERTIFICATE-----"));
+        // generated once for both listeners.
+        assert_eq!(identity.cert_pem, load_identity(&TlsConfig { certificate: TlsCertificate::SelfSigned, client_ca: None }).unwrap().cert_pem);
+
+        let config = server_config(&identity).unwrap();
+        assert_eq!(vec![b"h2".to_vec(), b"http/1.1".to_vec()], config.alpn_protocols);
+
+        let with_client_ca = TlsIdentity { client_ca_pem: Some(identity.cert_pem.clone()), ..identity };
+        assert!(server_config(&with_client_ca).is_ok());
+    }
+}
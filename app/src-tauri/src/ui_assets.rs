@@ -0,0 +1,105 @@
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use rust_embed::{Embed, EmbeddedFile};
+use tower_http::services::ServeFile;
+use crate::config::Config;
+use crate::domain::bytes_to_hex;
+
+const INDEX: &str = "index.html";
+// vite names the bundles of this directory after their hash, so they never change.
+const HASHED_ASSETS: &str = "assets/";
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+const REVALIDATE: &str = "no-cache";
+
+/// The built frontend, embedded at compile time. Empty when `bun run build` didn't run before cargo.
+#[derive(Embed)]
+#[folder = "../dist"]
+#[allow_missing = true]
+struct Assets;
+
+/// Serves the embedded UI. The paths without a file extension are routes of the single page app, which get the index.
+pub async fn embedded_ui_handler(request: Request) -> Response {
+    let path = request.uri().path().trim_start_matches('/');
+    let path = if path.is_empty() { INDEX } else { path };
+    match Assets::get(path) {
+        Some(file) => file_response(path, file, request.headers()),
+        None if is_route(path) => embedded_index(request.headers()),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// The index page, for the API paths which the UI has a page for as well, like `/metrics`.
+pub async fn index_page(config: &Config, request: Request) -> Response {
+    match &config.static_dir {
+        Some(static_dir) => match ServeFile::new(format!("{}/{}", static_dir, INDEX)).try_call(request).await {
+            Ok(response) => response.map(Body::new),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+        None => embedded_index(request.headers()),
+    }
+}
+
+fn embedded_index(headers: &HeaderMap) -> Response {
+    match Assets::get(INDEX) {
+        Some(file) => file_response(INDEX, file, headers),
+        None => (StatusCode::NOT_FOUND, "The UI was not built into this binary").into_response(),
+    }
+}
+
+fn is_route(path: &str) -> bool {
+    !path.rsplit('/').next().unwrap_or_default().contains('.')
+}
+
+fn file_response(path: &str, file: EmbeddedFile, headers: &HeaderMap) -> Response {
+    let etag = format!("\"{}\"", bytes_to_hex(&file.metadata.sha256_hash()));
+    let cache_control = if path.starts_with(HASHED_ASSETS) { IMMUTABLE } else { REVALIDATE };
+    let not_modified = headers.get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|tags| tags.split(',').map(str::trim).any(|tag| tag == etag || tag == "*"));
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag), (header::CACHE_CONTROL, cache_control.to_string())]).into_response();
+    }
+    let headers = [
+        (header::CONTENT_TYPE, file.metadata.mimetype().to_string()),
+        (header::ETAG, etag),
+        (header::CACHE_CONTROL, cache_control.to_string()),
+    ];
+    (headers, file.data).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_route() {
+        assert!(is_route("traces"));
+        assert!(is_route("logs/checkout"));
+        assert!(!is_route("assets/index-3f2a.js"));
+        assert!(!is_route("favicon.ico"));
+    }
+
+    // any folder of the repo, the built UI is not there when the tests run.
+    #[derive(Embed)]
+    #[folder = "icons"]
+    struct TestAssets;
+
+    #[test]
+    fn test_file_response() {
+        let file = TestAssets::get("32x32.png").unwrap();
+        let response = file_response("32x32.png", file.clone(), &HeaderMap::new());
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("image/png", response.headers()[header::CONTENT_TYPE]);
+        assert_eq!(REVALIDATE, response.headers()[header::CACHE_CONTROL]);
+
+        let etag = response.headers()[header::ETAG].clone();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag);
+        assert_eq!(StatusCode::NOT_MODIFIED, file_response("32x32.png", file.clone(), &headers).status());
+
+        let response = file_response("assets/32x32-3f2a.png", file, &HeaderMap::new());
+        assert_eq!(IMMUTABLE, response.headers()[header::CACHE_CONTROL]);
+    }
+}
@@ -1,20 +1,47 @@
 use axum::extract::{State};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use axum::{http, Router};
+use axum::{http, middleware, Router};
 use axum::body::Bytes;
 use axum::routing::{get, post};
+use axum::handler::Handler;
+use axum::middleware::Next;
+use axum::serve::Listener;
+use tower::ServiceExt;
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+use tower_http::decompression::RequestDecompressionLayer;
 use tower_http::services::ServeDir;
 use prost::{DecodeError, Message as ProstMessage};
 use crate::{AppState};
+use crate::auth::{handle_login, require_ingest_token, require_ui_token};
+use crate::config::CorsConfig;
+use crate::limits::{limit_ingest, read_body};
 use crate::opentelemetry::proto::collector::logs::v1::{ExportLogsServiceRequest, ExportLogsServiceResponse};
 use crate::opentelemetry::proto::collector::metrics::v1::{ExportMetricsServiceRequest, ExportMetricsServiceResponse};
 use crate::opentelemetry::proto::collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse};
 use crate::server::shutdown_signal;
+#[cfg(unix)]
+use crate::server::bind_unix_socket;
+use crate::tls;
+use crate::event_stream::event_stream_handler;
+use crate::grpc_server::{multiplexed_grpc_service, otlp_shutdown, GrpcService};
+use crate::health::{healthz_handler, readyz_handler, set_otlp_status};
+use crate::metrics_exposition::metrics_exposition_handler;
+use crate::receivers::loki::handle_push;
+use crate::ui_assets::embedded_ui_handler;
+use crate::receivers::prometheus::handle_remote_write;
 use crate::websocket_hub::websocket_handler;
 
+pub const HTTP_PORT: u16 = 4318;
 const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
 const JSON_CONTENT_TYPE: &str = "application/json";
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+// the header of the grpc-web clients, which their CORS preflight requests ask for.
+const GRPC_WEB_HEADER: &str = "x-grpc-web";
+const CORS_ANY: &str = "*";
+// how long a browser keeps the preflight response.
+const CORS_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(3600);
 
 fn get_otlp_routes() -> Router<AppState> {
     Router::new()
@@ -24,33 +51,144 @@ fn get_otlp_routes() -> Router<AppState> {
 }
 
 pub async fn init_axum(state: AppState) -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "[::]:4318";
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    println!("Axum server listening on http://{}", addr);
-    let mut app = Router::new()
-        .route("/ws", get(websocket_handler))
-        .nest("/v1", get_otlp_routes());
+    let addr = format!("[::]:{}", HTTP_PORT);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    let tls_config = match &state.config.tls {
+        Some(tls) => Some(tls::server_config(&tls::load_identity(tls)?)?),
+        None => None,
+    };
+    let scheme = if tls_config.is_some() { "https" } else { "http" };
+    println!("Axum server listening on {}://{}", scheme, addr);
+    // the senders and the UI have their own tokens.
+    let mut otlp = get_otlp_routes()
+        .route_layer(middleware::from_fn_with_state(state.clone(), limit_ingest))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_ingest_token));
+    // outside of the token check, the preflight requests have no credentials.
+    if let Some(cors) = &state.config.cors {
+        otlp = otlp.layer(cors_layer(cors));
+    }
+    let ingest = Router::new()
+        .route("/api/v1/write", post(handle_remote_write))
+        .route("/loki/api/v1/push", post(handle_push).layer(RequestDecompressionLayer::new()))
+        .route_layer(middleware::from_fn_with_state(state.clone(), limit_ingest))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_ingest_token))
+        .nest("/v1", otlp);
 
-    if cfg!(feature = "docker") {
-        let static_dir = std::env::var("STATIC_DIR").unwrap_or_else(|_| "../dist".to_string());
-        let serve_dir = ServeDir::new(&static_dir)
-            .fallback(tower_http::services::ServeFile::new(format!("{}/index.html", static_dir)));
-        app = app.fallback_service(serve_dir);
+    let mut ui = Router::new()
+        .route("/ws", get(websocket_handler))
+        .route("/api/stream", get(event_stream_handler))
+        .route("/metrics", get(metrics_exposition_handler));
+    if state.config.serve_ui {
+        ui = match &state.config.static_dir {
+            Some(static_dir) => ui.fallback_service(ServeDir::new(static_dir)
+                .fallback(tower_http::services::ServeFile::new(format!("{}/index.html", static_dir)))),
+            // only the UI is compressed, the websocket and the event stream are not.
+            None => ui.fallback(embedded_ui_handler.layer(CompressionLayer::new())),
+        };
     }
+    let ui = ui.layer(middleware::from_fn_with_state(state.clone(), require_ui_token));
 
-    let app = app.with_state(state);
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    let app = Router::new()
+        .route("/api/login", post(handle_login))
+        // the probes of docker and the load balancers have no token.
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .merge(ingest)
+        .merge(ui);
+
+    let http_socket = state.config.http_socket.clone();
+    let mut app = app.with_state(state.clone());
+    if state.config.single_port {
+        println!("gRPC served on the http port");
+        let grpc_service = multiplexed_grpc_service(state.clone())?;
+        app = app.layer(middleware::from_fn_with_state(grpc_service, route_grpc));
+        set_otlp_status(&state.health_reporter, tonic_health::ServingStatus::Serving).await;
+        tokio::spawn(otlp_shutdown(state.health_reporter.clone()));
+    }
+    #[cfg(unix)]
+    {
+        if let Some(path) = http_socket {
+            let unix_listener = bind_unix_socket(&path)?;
+            println!("Axum server listening on unix://{}", path);
+            tokio::try_join!(serve(listener, app.clone(), tls_config.clone()), serve(unix_listener, app, tls_config))?;
+            return Ok(());
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        if http_socket.is_some() {
+            println!("Unix domain sockets are not supported on this platform.");
+        }
+    }
+    serve(listener, app, tls_config).await?;
     Ok(())
 }
 
+async fn serve<L>(listener: L, app: Router, tls_config: Option<rustls::ServerConfig>) -> std::io::Result<()>
+    where L: Listener,
+          L::Addr: std::fmt::Debug {
+    match tls_config {
+        Some(tls_config) => tls::serve_tls(listener, app, tls_config).await,
+        None => axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown_signal())
+            .await,
+    }
+}
+
+// in the single port mode, the grpc and grpc-web requests go to the tonic services, whatever their path.
+async fn route_grpc(State(grpc_service): State<GrpcService>, request: axum::extract::Request, next: Next) -> Response {
+    if !is_grpc(request.headers()) {
+        return next.run(request).await;
+    }
+    match grpc_service.oneshot(request.map(tonic::body::Body::new)).await {
+        Ok(response) => response.map(axum::body::Body::new),
+        Err(infallible) => match infallible {},
+    }
+}
+
+fn is_grpc(headers: &http::HeaderMap) -> bool {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    header(http::header::CONTENT_TYPE).starts_with(GRPC_CONTENT_TYPE)
+        || header(http::header::ACCESS_CONTROL_REQUEST_HEADERS).to_ascii_lowercase().contains(GRPC_WEB_HEADER)
+}
+
+// the browser exporters, like the opentelemetry-js OTLP http ones, post from the origin of their page.
+pub(crate) fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    let allow_origin = if config.allowed_origins.iter().any(|origin| origin == CORS_ANY) {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(parse_cors_values(&config.allowed_origins, |origin| origin.parse::<http::HeaderValue>()))
+    };
+    let allow_headers = if config.allowed_headers.is_empty() {
+        AllowHeaders::mirror_request()
+    } else if config.allowed_headers.iter().any(|header| header == CORS_ANY) {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(parse_cors_values(&config.allowed_headers, |header| header.parse::<http::HeaderName>()))
+    };
+    let allow_methods = AllowMethods::list(parse_cors_values(&config.allowed_methods, |method| method.to_uppercase().parse::<http::Method>()));
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_headers(allow_headers)
+        .allow_methods(allow_methods)
+        .max_age(CORS_MAX_AGE)
+}
+
+fn parse_cors_values<T, E: std::fmt::Display>(values: &[String], parse: impl Fn(&str) -> Result<T, E>) -> Vec<T> {
+    values.iter()
+        .filter_map(|value| parse(value)
+            .inspect_err(|e| println!("Ignoring the CORS value '{}': {}", value, e))
+            .ok())
+        .collect()
+}
+
 // Resources:
 // Proto: https://github.com/open-telemetry/opentelemetry-proto/blob/main/opentelemetry/proto/collector/logs/v1/logs_service.proto
 // Collector-Go: https://github.com/open-telemetry/opentelemetry-collector/blob/main/receiver/otlpreceiver/otlphttp.go
 // Aspire-Dashboard-C#: https://github.com/dotnet/aspire/blob/main/src/Aspire.Dashboard/Otlp/Http/OtlpHttpEndpointsBuilder.cs
 async fn handle_logs(State(state): State<AppState>, request: axum::extract::Request) -> impl IntoResponse {
     let r = extract_request(request,
+                            state.config.limits.max_request_size,
                             ExportLogsServiceRequest::decode,
                             |body| serde_json::from_slice(&body)).await;
     match r {
@@ -72,6 +210,7 @@ async fn handle_logs(State(state): State<AppState>, request: axum::extract::Requ
 
 async fn handle_traces(State(state): State<AppState>, request: axum::extract::Request) -> impl IntoResponse {
     let r = extract_request(request,
+                            state.config.limits.max_request_size,
                             ExportTraceServiceRequest::decode,
                             |body| serde_json::from_slice(&body)).await;
     match r {
@@ -93,6 +232,7 @@ async fn handle_traces(State(state): State<AppState>, request: axum::extract::Re
 
 async fn handle_metrics(State(state): State<AppState>, request: axum::extract::Request) -> impl IntoResponse {
     let r = extract_request(request,
+                            state.config.limits.max_request_size,
                             ExportMetricsServiceRequest::decode,
                             |body| serde_json::from_slice(&body)).await;
     match r {
@@ -116,7 +256,8 @@ fn read_content_type(headers: &http::header::HeaderMap) -> Option<&str> {
     headers.get("content-type").and_then(|v| v.to_str().ok())
 }
 
-async fn extract_request<T, TProtoExtractor, TJsonExtractor>(request: axum::extract::Request,
+pub(crate) async fn extract_request<T, TProtoExtractor, TJsonExtractor>(request: axum::extract::Request,
+                                                             max_size: usize,
                                                              extractor_proto: TProtoExtractor,
                                                              extractor_json: TJsonExtractor) -> Result<T, Response>
     where TProtoExtractor: FnOnce(Bytes) -> Result<T, DecodeError>,
@@ -125,10 +266,7 @@ async fn extract_request<T, TProtoExtractor, TJsonExtractor>(request: axum::extr
     let content_type = read_content_type(&parts.headers);
     match content_type {
         Some(ct) => {
-            let body = match axum::body::to_bytes(body, usize::MAX).await {
-                Ok(bytes) => bytes,
-                Err(_) => return Err((StatusCode::BAD_REQUEST, "Failed to read request body").into_response()),
-            };
+            let body = read_body(body, max_size).await?;
             if ct == PROTOBUF_CONTENT_TYPE {
                 extractor_proto(body).map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to decode protobuf request body: {}", e)).into_response())
             } else if ct == JSON_CONTENT_TYPE {
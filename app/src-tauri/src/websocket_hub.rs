@@ -1,117 +1,405 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use axum::body::Bytes;
 use axum::extract::{State, WebSocketUpgrade};
-use axum::extract::ws::{Message, Utf8Bytes, WebSocket};
+use axum::extract::ws::{Message, WebSocket};
 use axum::response::IntoResponse;
 use futures::{SinkExt, StreamExt};
+use futures::stream::SplitSink;
 use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use uuid::Uuid;
 use crate::app_state::AppState;
-use crate::subscription_manager::Topic;
+use crate::message_encoding::MessageEncoding;
+use crate::pause_buffer::PauseBuffer;
+use crate::subscription_manager::{ClientId, Topic, TopicMessage};
+use crate::telemetry_store::{Sequence, StoreStats};
+
+const PROTOCOL_VERSION: u32 = 1;
+const DEFAULT_QUERY_LIMIT: usize = 1000;
+// the copies of an event for overlapping subscriptions arrive close to each other, well within the capacity of the topic channels.
+const DEDUPE_WINDOW: usize = 1024;
 
 pub async fn websocket_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_websocket(socket, state))
+    // clients can ask for a binary encoding through the websocket sub-protocol, json stays the default.
+    let ws = ws.protocols(MessageEncoding::PROTOCOLS);
+    let encoding = ws.selected_protocol()
+        .and_then(|protocol| protocol.to_str().ok())
+        .and_then(MessageEncoding::from_protocol)
+        .unwrap_or_default();
+    ws.on_upgrade(move |socket| handle_websocket(socket, state, encoding))
 }
 
 #[derive(Deserialize)]
 enum Command {
     Subscribe(Topic),
     Unsubscribe(Topic),
+    Query(QueryCommand),
+    Clear,
+    Pause,
+    Resume,
+    GetStats,
+}
+
+#[derive(Deserialize)]
+struct QueryCommand {
+    topic: Topic,
+    after: Option<Sequence>,
+    limit: Option<usize>,
+}
+
+fn protocol_version() -> u32 {
+    PROTOCOL_VERSION
 }
 
 #[derive(Deserialize)]
 struct WebSocketCommand {
+    // commands without a version are treated as the current one.
+    #[serde(default = "protocol_version")]
+    v: u32,
+    #[serde(default)]
+    id: Option<String>,
     command: Command
 }
 
+// used to read the request id back when the command itself can't be parsed.
+#[derive(Deserialize)]
+struct CommandEnvelope {
+    #[serde(default)]
+    id: Option<String>,
+}
+
 #[derive(Serialize)]
 struct ConnectResponse {
     client_id: String
 }
 
-async fn handle_websocket(socket: WebSocket, state: AppState) {
+#[derive(Serialize)]
+struct CommandResponse {
+    v: u32,
+    id: Option<String>,
+    #[serde(flatten)]
+    outcome: CommandOutcome,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CommandOutcome {
+    Result(CommandResult),
+    Error(CommandError),
+}
+
+#[derive(Serialize)]
+enum CommandResult {
+    Ok,
+    Messages(Vec<TopicMessage>),
+    Stats(ClientStats),
+}
+
+#[derive(Serialize)]
+struct ClientStats {
+    #[serde(flatten)]
+    store: StoreStats,
+    clients: usize,
+    paused: bool,
+}
+
+#[derive(Serialize)]
+enum ErrorCode {
+    InvalidCommand,
+    UnsupportedVersion,
+    NotSubscribed,
+}
+
+#[derive(Serialize)]
+struct CommandError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl CommandError {
+    fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        CommandError { code, message: message.into() }
+    }
+}
+
+/// Everything which goes to the websocket client passes through the dispatch queue,
+/// so events, responses and pause/resume switches keep their order.
+enum Outgoing {
+    Event(TopicMessage),
+    Message(Message),
+    Pause,
+    Resume,
+}
+
+struct ClientSession {
+    client_id: ClientId,
+    state: AppState,
+    queue: UnboundedSender<Outgoing>,
+    topic_listeners: HashMap<Topic, tokio::task::JoinHandle<()>>,
+    paused: bool,
+}
+
+impl ClientSession {
+    async fn handle_text(&mut self, content: &str) -> CommandResponse {
+        let command = match serde_json::from_str::<WebSocketCommand>(content) {
+            Ok(command) => command,
+            Err(e) => {
+                let id = serde_json::from_str::<CommandEnvelope>(content).ok().and_then(|envelope| envelope.id);
+                let error = CommandError::new(ErrorCode::InvalidCommand, format!("Unable to parse command: {}", e));
+                return CommandResponse { v: PROTOCOL_VERSION, id, outcome: CommandOutcome::Error(error) };
+            }
+        };
+        let outcome = if command.v != PROTOCOL_VERSION {
+            let error = CommandError::new(ErrorCode::UnsupportedVersion,
+                                          format!("Unsupported protocol version {}, expected {}", command.v, PROTOCOL_VERSION));
+            CommandOutcome::Error(error)
+        } else {
+            match self.handle_command(command.command).await {
+                Ok(result) => CommandOutcome::Result(result),
+                Err(error) => CommandOutcome::Error(error),
+            }
+        };
+        CommandResponse { v: PROTOCOL_VERSION, id: command.id, outcome }
+    }
+
+    async fn handle_command(&mut self, command: Command) -> Result<CommandResult, CommandError> {
+        match command {
+            Command::Subscribe(topic) => {
+                if !self.topic_listeners.contains_key(&topic) {
+                    let mut rx = self.state.subscription_manager.write().await.subscribe(topic.clone(), self.client_id.clone());
+                    // create a task to listen for events on this topic.
+                    let queue = self.queue.clone();
+                    let listen_handle = tokio::spawn(async move {
+                        while let Ok(message) = rx.recv().await {
+                            if queue.send(Outgoing::Event(message)).is_err() {
+                                println!("Unable to send message to event queue.");
+                                break;
+                            }
+                        }
+                    });
+                    self.topic_listeners.insert(topic, listen_handle);
+                }
+                Ok(CommandResult::Ok)
+            },
+            Command::Unsubscribe(topic) => {
+                match self.topic_listeners.remove(&topic) {
+                    Some(handle) => {
+                        println!("Unsubscribe {} from: {}", self.client_id, topic);
+                        handle.abort();
+                        self.state.subscription_manager.write().await.unsubscribe(&self.client_id, &topic);
+                        Ok(CommandResult::Ok)
+                    },
+                    None => Err(CommandError::new(ErrorCode::NotSubscribed, format!("Not subscribed to: {}", topic)))
+                }
+            },
+            Command::Query(query) => {
+                let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+                let messages = self.state.telemetry_store.read().await.query(&query.topic, query.after, limit);
+                Ok(CommandResult::Messages(messages))
+            },
+            Command::Clear => {
+                self.state.telemetry_store.write().await.clear();
+                Ok(CommandResult::Ok)
+            },
+            Command::Pause => {
+                self.paused = true;
+                self.enqueue(Outgoing::Pause);
+                Ok(CommandResult::Ok)
+            },
+            Command::Resume => {
+                self.paused = false;
+                self.enqueue(Outgoing::Resume);
+                Ok(CommandResult::Ok)
+            },
+            Command::GetStats => {
+                let store = self.state.telemetry_store.read().await.stats();
+                let clients = self.state.subscription_manager.read().await.client_count();
+                Ok(CommandResult::Stats(ClientStats { store, clients, paused: self.paused }))
+            }
+        }
+    }
+
+    fn enqueue(&self, outgoing: Outgoing) {
+        if self.queue.send(outgoing).is_err() {
+            println!("Unable to send message to event queue.");
+        }
+    }
+
+    async fn close(self) {
+        // stop all tasks
+        for handle in self.topic_listeners.values() {
+            handle.abort();
+        }
+        self.state.subscription_manager.write().await.unsubscribe_client(&self.client_id);
+    }
+}
+
+/// The sequences of the recently dispatched events. Each subscription of a client has its own channel,
+/// so an event matching both `logs` and `logs/checkout` arrives twice and only goes out once.
+struct RecentSequences {
+    order: VecDeque<Sequence>,
+    seen: HashSet<Sequence>,
+}
+
+impl RecentSequences {
+    fn new() -> Self {
+        Self { order: VecDeque::new(), seen: HashSet::new() }
+    }
+
+    // false for a sequence which was already dispatched, the events which weren't stored have none.
+    fn insert(&mut self, seq: Sequence) -> bool {
+        if seq == 0 {
+            return true;
+        }
+        if !self.seen.insert(seq) {
+            return false;
+        }
+        self.order.push_back(seq);
+        if self.order.len() > DEDUPE_WINDOW {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+async fn dispatch(mut sender: SplitSink<WebSocket, Message>,
+                  mut queue: UnboundedReceiver<Outgoing>,
+                  encoding: MessageEncoding,
+                  pause_buffer_size: usize) {
+    // while paused the events are kept in the buffer and sent on resume.
+    let mut pause_buffer: Option<PauseBuffer> = None;
+    let mut dispatched = RecentSequences::new();
+    while let Some(outgoing) = queue.recv().await {
+        let mut messages = Vec::new();
+        match outgoing {
+            Outgoing::Event(event) if !dispatched.insert(event.seq()) => {},
+            Outgoing::Event(event) => match pause_buffer.as_mut() {
+                Some(buffer) => buffer.push(event),
+                None => messages.push(encoding.encode(&event).expect("Unable to serialize event.")),
+            },
+            Outgoing::Message(message) => messages.push(message),
+            Outgoing::Pause => {
+                pause_buffer.get_or_insert_with(|| PauseBuffer::new(pause_buffer_size));
+            },
+            Outgoing::Resume => {
+                if let Some(buffer) = pause_buffer.take() {
+                    let (events, summary) = buffer.flush();
+                    for event in events {
+                        messages.push(encoding.encode(&event).expect("Unable to serialize event."));
+                    }
+                    if let Some(summary) = summary {
+                        messages.push(encoding.encode(&summary).expect("Unable to serialize summary."));
+                    }
+                }
+            }
+        }
+        for message in messages {
+            if sender.send(message).await.is_err() {
+                println!("Unable to send message to websocket client.");
+                return;
+            }
+        }
+    }
+}
+
+async fn handle_websocket(socket: WebSocket, state: AppState, encoding: MessageEncoding) {
     let (mut sender, mut receiver) = socket.split();
     // handle new connections.
     let client_id = Uuid::now_v7().to_string();
     let response = ConnectResponse { client_id: client_id.clone() };
-    let msg = serde_json::to_string(&response).expect("Unable to serialize response.");
-    sender.send(Message::Text(Utf8Bytes::from(msg))).await.expect("Unable to send response.");
+    let msg = encoding.encode(&response).expect("Unable to serialize response.");
+    sender.send(msg).await.expect("Unable to send response.");
 
     // dispatch messages to web socket.
-    let (message_queue_sender, mut message_queue_receiver) = tokio::sync::mpsc::unbounded_channel::<Message>();
-    let dispatch_handle = tokio::spawn(async move {
-        while let Some(message) = message_queue_receiver.recv().await {
-            match sender.send(message).await {
-                Ok(_) => {},
-                Err(_) => {
-                    println!("Unable to send message to websocket client.");
-                    break;
-                }
-            }
-        }
-    });
-    // dispatch pong messages back to websocket client.
-    let mut topic_listeners: HashMap<Topic, tokio::task::JoinHandle<()>> = HashMap::new();
+    let (message_queue_sender, message_queue_receiver) = tokio::sync::mpsc::unbounded_channel::<Outgoing>();
+    let pause_buffer_size = state.config.pause_buffer_size;
+    let dispatch_handle = tokio::spawn(dispatch(sender, message_queue_receiver, encoding, pause_buffer_size));
+
+    let mut session = ClientSession {
+        client_id,
+        state,
+        queue: message_queue_sender,
+        topic_listeners: HashMap::new(),
+        paused: false,
+    };
 
     // listen for messages from websocket client
     while let Some(Ok(message)) = receiver.next().await {
-        match message {
-            Message::Binary(msg) => {
-                if msg.len() == 1 && msg[0] == 0x09 {
-                    let message_queue_sender = message_queue_sender.clone();
-                    match message_queue_sender.send(Message::Binary(Bytes::from_static(&[0x0A]))) {
-                        Ok(_) => {},
-                        Err(_) => {
-                            println!("Unable to send message to event queue.");
-                            break;
-                        }
-                    }
-                }
-            },
+        let reply = match message {
+            // dispatch pong messages back to websocket client.
+            Message::Binary(msg) if msg.len() == 1 && msg[0] == 0x09 => Message::Binary(Bytes::from_static(&[0x0A])),
             Message::Text(content) => {
-                if let Ok(command) = serde_json::from_str::<WebSocketCommand>(&content) {
-                    match command.command {
-                        Command::Subscribe(topic) => {
-                            if !topic_listeners.contains_key(&topic) {
-                                let mut rx = state.subscription_manager.write().await.subscribe(topic.clone(), client_id.clone());
-                                // create a task to listen for events on this topic.
-                                let message_queue_sender = message_queue_sender.clone();
-                                let listen_handle = tokio::spawn(async move {
-                                    while let Ok(message) = rx.recv().await {
-                                        let json = serde_json::to_string(&message).expect("Unable to serialize event.");
-                                        let msg = Utf8Bytes::from(json);
-                                        match message_queue_sender.send(Message::Text(msg)) {
-                                            Ok(_) => {},
-                                            Err(_) => {
-                                                println!("Unable to send message to event queue.");
-                                                break;
-                                            }
-                                        }
-                                    }
-                                });
-
-                                topic_listeners.insert(topic.clone(), listen_handle);
-                            }
-                        },
-                        Command::Unsubscribe(topic) => {
-                            if let Some(handle) = topic_listeners.remove(&topic) {
-                                println!("Unsubscribe {} from: {}", client_id, topic);
-                                handle.abort();
-                                state.subscription_manager.write().await.unsubscribe(&client_id, &topic);
-                            }
-                        }
-                    }
-                }
+                let response = session.handle_text(&content).await;
+                encoding.encode(&response).expect("Unable to serialize response.")
             }
-            _ => {}
+            _ => continue
+        };
+        if session.queue.send(Outgoing::Message(reply)).is_err() {
+            println!("Unable to send message to event queue.");
+            break;
         }
     }
 
-    // stop all tasks
-    for handle in topic_listeners.values() {
-        handle.abort();
-    }
-    state.subscription_manager.write().await.unsubscribe_client(&client_id);
-    drop(message_queue_sender);
+    session.close().await;
     let _ = dispatch_handle.await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        let command = serde_json::from_str::<WebSocketCommand>(r#"{"v":1,"id":"1","command":{"Query":{"topic":"logs","limit":10}}}"#).unwrap();
+        assert_eq!(Some("1".to_string()), command.id);
+        assert!(matches!(command.command, Command::Query(QueryCommand { limit: Some(10), after: None, .. })));
+
+        // commands sent without version and request id.
+        let command = serde_json::from_str::<WebSocketCommand>(r#"{"command":{"Subscribe":"logs"}}"#).unwrap();
+        assert_eq!(PROTOCOL_VERSION, command.v);
+        assert_eq!(None, command.id);
+
+        let command = serde_json::from_str::<WebSocketCommand>(r#"{"command":"GetStats"}"#).unwrap();
+        assert!(matches!(command.command, Command::GetStats));
+    }
+
+    #[test]
+    fn test_recent_sequences() {
+        let mut dispatched = RecentSequences::new();
+        assert!(dispatched.insert(1));
+        assert!(dispatched.insert(2));
+        assert!(!dispatched.insert(1));
+        assert!(dispatched.insert(0));
+        assert!(dispatched.insert(0));
+
+        for seq in 3..=DEDUPE_WINDOW as Sequence + 1 {
+            assert!(dispatched.insert(seq));
+        }
+        // the oldest ones left the window.
+        assert!(dispatched.insert(1));
+        assert!(!dispatched.insert(DEDUPE_WINDOW as Sequence));
+    }
+
+    #[test]
+    fn test_response_serialization() {
+        let response = CommandResponse { v: 1, id: Some("1".to_string()), outcome: CommandOutcome::Result(CommandResult::Ok) };
+        assert_eq!(r#"{"v":1,"id":"1","result":"Ok"}"#, serde_json::to_string(&response).unwrap());
+
+        let error = CommandError::new(ErrorCode::NotSubscribed, "Not subscribed to: logs");
+        let response = CommandResponse { v: 1, id: None, outcome: CommandOutcome::Error(error) };
+        assert_eq!(r#"{"v":1,"id":null,"error":{"code":"NotSubscribed","message":"Not subscribed to: logs"}}"#,
+                   serde_json::to_string(&response).unwrap());
+    }
+
+    #[test]
+    fn test_response_binary_serialization() {
+        let stats = ClientStats { store: StoreStats::default(), clients: 2, paused: false };
+        let response = CommandResponse { v: 1, id: None, outcome: CommandOutcome::Result(CommandResult::Stats(stats)) };
+        let encoded = MessageEncoding::MessagePack.encode(&response).unwrap();
+        let Message::Binary(bytes) = encoded else { panic!("Expected binary message") };
+        let decoded: serde_json::Value = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(serde_json::to_value(&response).unwrap(), decoded);
+    }
+}
@@ -0,0 +1,17 @@
+// set by the desktop app before the UI loads, the browsers which the http port serves have none.
+export interface DesktopSettings {
+    // the UI token, which the desktop window has no cookie of.
+    uiToken: string | null;
+    // the url which opens the UI in other browsers, when the desktop app serves it.
+    uiUrl: string | null;
+}
+
+declare global {
+    interface Window {
+        __INSPECTOR__?: DesktopSettings;
+    }
+}
+
+export function desktopSettings(): DesktopSettings | undefined {
+    return window.__INSPECTOR__;
+}
@@ -1,6 +1,7 @@
 import {LogDto} from "../domain/logs.ts";
 import {SpanDto} from "../domain/traces.ts";
 import {MetricDto} from "../domain/metrics.ts";
+import {desktopSettings} from "./desktop-settings.ts";
 
 export interface MessageHandlers {
     onLogReceived: (log: LogDto) => void;
@@ -18,7 +19,9 @@ export class WebSocketService {
     connect() {
         const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
         const host = window.location.hostname || 'localhost';
-        this.socket = new WebSocket(`${protocol}//${host}:4318/ws`);
+        const token = desktopSettings()?.uiToken;
+        const query = token ? `?token=${encodeURIComponent(token)}` : '';
+        this.socket = new WebSocket(`${protocol}//${host}:4318/ws${query}`);
         this.socket.binaryType = "blob";
 
         this.socket.addEventListener('message', this.onMessage);
@@ -162,20 +165,25 @@ export class WebSocketService {
         return false;
     }
 
+    // topics are hierarchical, e.g. `logs/<service>/<severity>`, the first segment is the kind of telemetry.
+    private static topicRoot(topic: string): string {
+        return topic.split('/')[0];
+    }
+
     private static isConnectedEvent(data: any): data is { client_id: string } {
         return 'client_id' in data;
     }
 
     private static isLogsEvent(data: any): data is Message<LogDto> {
-        return 'topic' in data && data.topic === 'logs' && 'payload' in data;
+        return 'topic' in data && WebSocketService.topicRoot(data.topic) === 'logs' && 'payload' in data;
     }
 
     private static isTracesEvent(data: any): data is Message<SpanDto> {
-        return 'topic' in data && data.topic === 'traces' && 'payload' in data;
+        return 'topic' in data && WebSocketService.topicRoot(data.topic) === 'traces' && 'payload' in data;
     }
 
     private static isMetricsEvent(data: any): data is Message<MetricDto> {
-        return 'topic' in data && data.topic === 'metrics' && 'payload' in data;
+        return 'topic' in data && WebSocketService.topicRoot(data.topic) === 'metrics' && 'payload' in data;
     }
 }
 